@@ -0,0 +1,136 @@
+use std::{io::Read, path::Path, sync::Arc};
+
+use console::style;
+
+use crate::{cli::CLI, error::SrpError, scheduler::RequestScheduler};
+
+//Outcome of archiving a single post of a batch
+#[derive(Debug)]
+pub struct BatchResult {
+    pub url: String,
+    //Where the output was written, "-" for stdout
+    pub output: String,
+    pub result: Result<(), SrpError>,
+}
+
+//Archive every url listed in cli.url (a file, or "-" for stdin) with the options of the cli.
+//Every post gets it's own output (see output_path), failures don't stop the batch.
+//All requests share one scheduler, so the rate limit holds for the whole batch.
+pub async fn run(cli: &CLI) -> Result<Vec<BatchResult>, SrpError> {
+    let input = if cli.url == "-" {
+        let mut input = String::new();
+        std::io::stdin().read_to_string(&mut input)?;
+        input
+    } else {
+        std::fs::read_to_string(&cli.url).map_err(|e| {
+            SrpError::Filesystem(format!("Failed to read the batch file {}: {e}", cli.url))
+        })?
+    };
+    let urls = parse_list(&input);
+    if urls.is_empty() {
+        return Err(SrpError::Validation(format!("No urls in {}", cli.url)));
+    }
+
+    let first_url = CLI::parse_url_with_host(urls[0].clone(), cli.host.as_deref())
+        .map_or_else(|_| String::new(), |o| o.0);
+    let scheduler = Arc::new(RequestScheduler::new(cli.client.build(&first_url)?));
+
+    Ok(archive_urls(cli, &scheduler, urls).await)
+}
+
+//Archive every post url with the options of the cli, see run
+pub async fn archive_urls(
+    cli: &CLI,
+    scheduler: &Arc<RequestScheduler>,
+    urls: Vec<String>,
+) -> Vec<BatchResult> {
+    let total = urls.len();
+    let mut results = Vec::new();
+    for (idx, url) in urls.into_iter().enumerate() {
+        cli.print_infom(format!(
+            "{} [{}/{total}] {url}",
+            style("Batch").bold(),
+            idx + 1
+        ));
+        let output = if cli.save_to_file {
+            output_path(&cli.save_path, &url, idx)
+        } else {
+            String::from("-")
+        };
+        let result = match CLI::parse_url_with_host(url.clone(), cli.host.as_deref()) {
+            Ok((post_url, base_url)) => {
+                let post_cli = CLI {
+                    url: post_url,
+                    base_url,
+                    save_path: output.clone(),
+                    ..cli.clone()
+                };
+                crate::archive_with(&post_cli, Arc::clone(scheduler)).await
+            }
+            Err(e) => Err(e),
+        };
+        if let Err(e) = &result {
+            cli.print_warning(format!("{url} failed: {e}"));
+        }
+        results.push(BatchResult {
+            url,
+            output,
+            result,
+        });
+    }
+    results
+}
+
+//One url per line, empty lines and comments (starting with #) are skipped
+pub fn parse_list(input: &str) -> Vec<String> {
+    input
+        .lines()
+        .map(|o| o.split_once(" #").map_or(o, |o| o.0).trim())
+        .filter(|o| !o.is_empty() && !o.starts_with('#'))
+        .map(str::to_owned)
+        .collect()
+}
+
+//Output path of a post, the post's id is added to save_path
+//ex. output.txt, https://reddit.com/r/foo/comments/abc/title -> output_abc.txt
+//idx is used when the url doesn't have an id
+pub fn output_path(save_path: &str, url: &str, idx: usize) -> String {
+    let id = url
+        .split('/')
+        .skip_while(|o| *o != "comments")
+        .nth(1)
+        .filter(|o| !o.is_empty() && o.chars().all(char::is_alphanumeric))
+        .map_or_else(|| (idx + 1).to_string(), str::to_owned);
+
+    let path = Path::new(save_path);
+    let stem = path
+        .file_stem()
+        .and_then(|o| o.to_str())
+        .unwrap_or("output");
+    let name = match path.extension().and_then(|o| o.to_str()) {
+        Some(ext) => format!("{stem}_{id}.{ext}"),
+        None => format!("{stem}_{id}"),
+    };
+    path.with_file_name(name).to_string_lossy().into_owned()
+}
+
+//Print a table with the result of every post
+pub fn print_summary(results: &[BatchResult]) {
+    let failed = results.iter().filter(|o| o.result.is_err()).count();
+    let url_width = results.iter().map(|o| o.url.len()).max().unwrap_or(0);
+    println!(
+        "{}",
+        style(format!(
+            "Batch summary: {} succeeded, {failed} failed",
+            results.len() - failed
+        ))
+        .bold()
+    );
+    for (idx, o) in results.iter().enumerate() {
+        let (status, info) = match &o.result {
+            Ok(()) => (style("ok    ").green(), o.output.clone()),
+            Err(e) => (style("failed").red(), e.to_string()),
+        };
+        println!("{:>4} {status} {:url_width$} {info}", idx + 1, o.url);
+    }
+}
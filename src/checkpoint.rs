@@ -0,0 +1,185 @@
+use std::{
+    collections::HashSet,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+};
+
+use crate::{
+    cli::CLI, element::Element, error::SrpError, offline, session::FetchSession, url::RedditUrl,
+};
+
+//Crawl state of a post, saved in the tmp dir while 'more' elements are requested,
+//so an interrupted run can be resumed with --resume.
+//Every post has it's own dir (see Checkpoint::dir_for), with the same layout as --save-tmp
+//(raw.json and more_elements/), plus:
+//  state.json - the url the checkpoint belongs to
+//  done.txt   - ids already requested, one per line
+//Responses are written as they arrive, so a killed run can be resumed too
+#[derive(Debug)]
+pub struct Checkpoint {
+    dir: PathBuf,
+    done: Mutex<HashSet<String>>,
+    //Index of the next morechildren_N.json file
+    next_batch: AtomicUsize,
+}
+
+impl Checkpoint {
+    //Default location of the checkpoints
+    pub fn default_dir() -> PathBuf {
+        Path::new(crate::utils::TMP_DIR).join("checkpoint")
+    }
+
+    //Dir of the checkpoint of url, ex. tmp/checkpoint/<post id>
+    //Comment permalinks get their own, ex. tmp/checkpoint/<post id>_<comment id>
+    pub fn dir_for(url: &str, host: Option<&str>) -> PathBuf {
+        let name = match RedditUrl::parse(url, host) {
+            Ok(o) if o.post_id().is_some() => match (o.post_id(), o.comment()) {
+                (Some(post), Some(comment)) => format!("{post}_{comment}"),
+                (post, _) => post.unwrap_or_default().to_owned(),
+            },
+            _ => url
+                .chars()
+                .map(|o| if o.is_ascii_alphanumeric() { o } else { '_' })
+                .collect(),
+        };
+        Self::default_dir().join(name)
+    }
+
+    //Start a new checkpoint for url, deleting any previous one in dir
+    pub fn create(dir: &Path, url: &str) -> Result<Self, SrpError> {
+        if dir.exists() {
+            std::fs::remove_dir_all(dir)?;
+        }
+        std::fs::create_dir_all(dir.join("more_elements"))?;
+        write_atomic(
+            &dir.join("state.json"),
+            &json::object! { url: url }.pretty(1),
+        )?;
+        Ok(Self {
+            dir: dir.to_owned(),
+            done: Mutex::new(HashSet::new()),
+            next_batch: AtomicUsize::new(0),
+        })
+    }
+
+    //Open the checkpoint in dir, None if there isn't one or it belongs to another url
+    pub fn open(dir: &Path, url: &str) -> Result<Option<Self>, SrpError> {
+        let Ok(state) = std::fs::read_to_string(dir.join("state.json")) else {
+            return Ok(None);
+        };
+        if json::parse(&state)?["url"].as_str() != Some(url) || !dir.join("raw.json").exists() {
+            return Ok(None);
+        }
+
+        let done = std::fs::read_to_string(dir.join("done.txt"))
+            .unwrap_or_default()
+            .lines()
+            .filter(|o| !o.is_empty())
+            .map(str::to_owned)
+            .collect::<HashSet<_>>();
+
+        let next_batch = std::fs::read_dir(dir.join("more_elements"))?
+            .filter_map(|o| {
+                o.ok()?
+                    .file_name()
+                    .to_str()?
+                    .strip_prefix("morechildren_")?
+                    .strip_suffix(".json")?
+                    .parse::<usize>()
+                    .ok()
+            })
+            .max()
+            .map_or(0, |o| o + 1);
+
+        Ok(Some(Self {
+            dir: dir.to_owned(),
+            done: Mutex::new(done),
+            next_batch: AtomicUsize::new(next_batch),
+        }))
+    }
+
+    //Rebuild the element tree from the checkpoint
+    pub fn load(&self, cli: &CLI, session: &FetchSession) -> Result<Vec<Element>, SrpError> {
+        let json_data = serde_json::from_str(&offline::read(&self.dir.join("raw.json"))?)?;
+        let elements = crate::parse(json_data, session)?;
+        offline::load_more_elements(cli, session, elements, &self.dir.join("more_elements"))
+    }
+
+    pub fn save_raw(&self, json_data: &serde_json::Value) -> Result<(), SrpError> {
+        write_atomic(
+            &self.dir.join("raw.json"),
+            &serde_json::to_string_pretty(json_data)?,
+        )
+    }
+
+    pub fn is_done(&self, id: &str) -> bool {
+        self.lock().contains(id)
+    }
+
+    pub fn done_len(&self) -> usize {
+        self.lock().len()
+    }
+
+    //Save a morechildren response and mark its ids as done
+    pub fn save_batch(&self, ids: &[String], data: &str) -> Result<(), SrpError> {
+        let idx = self.next_batch.fetch_add(1, Ordering::SeqCst);
+        write_atomic(
+            &self
+                .dir
+                .join("more_elements")
+                .join(format!("morechildren_{idx}.json")),
+            data,
+        )?;
+        self.mark_done(ids)
+    }
+
+    //Save the response of a single 'more' element and mark it as done
+    pub fn save_single(&self, id: &str, data: &str) -> Result<(), SrpError> {
+        write_atomic(
+            &self.dir.join("more_elements").join(format!("{id}.json")),
+            data,
+        )?;
+        self.mark_done(&[id.to_owned()])
+    }
+
+    //Delete the dir of the checkpoint, and the dirs above it if they're left empty
+    pub fn remove(&self) -> Result<(), SrpError> {
+        //Could be already deleted with the tmp dir (--delete-tmp)
+        if self.dir.exists() {
+            std::fs::remove_dir_all(&self.dir)?;
+        }
+        for dir in self.dir.ancestors().skip(1).take(2) {
+            //Only deletes empty dirs
+            let _ = std::fs::remove_dir(dir);
+        }
+        Ok(())
+    }
+
+    fn mark_done(&self, ids: &[String]) -> Result<(), SrpError> {
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.dir.join("done.txt"))?;
+        file.write_all((ids.join("\n") + "\n").as_bytes())?;
+        self.lock().extend(ids.iter().cloned());
+        Ok(())
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashSet<String>> {
+        self.done
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+}
+
+//Write through a temporary file, so an interrupted run never leaves a half written file
+fn write_atomic(path: &Path, data: &str) -> Result<(), SrpError> {
+    let tmp_path = path.with_extension("part");
+    std::fs::write(&tmp_path, data)?;
+    std::fs::rename(tmp_path, path)?;
+    Ok(())
+}
@@ -1,7 +1,19 @@
-use std::{fmt::Display, io::Write, process::exit};
+use std::{fmt::Display, io::Write, process::exit, time::Duration};
 
 use console::style;
 
+use crate::{
+    client::ClientConfig,
+    date::{DateFormat, Timezone},
+    diff::Diff,
+    element::Format,
+    error::SrpError,
+    listing::{Listing, ListingSort, TIME_RANGES},
+    profile::Profile,
+    url::{RedditUrl, REDDIT_ORIGIN},
+    watch::Watch,
+};
+
 //Allow this, bcs when running tests compiler throws a dead code warning which is not true.
 #[derive(PartialEq, Eq, Debug, Clone)]
 #[allow(clippy::upper_case_acronyms)] //my preference
@@ -10,6 +22,9 @@ pub struct CLI {
     pub base_url: String,
     pub save_to_file: bool,
     pub save_path: String,
+    pub format: Format,
+    //Timezone and format of the dates in the Default and HTML outputs
+    pub dates: DateFormat,
     pub max_comments: usize,
     pub sort_style: ElementSort,
     pub filter: ElementFilter,
@@ -18,6 +33,35 @@ pub struct CLI {
     pub req_more_elements: bool,
     pub delete_tmp: bool,
     pub print_timestamps: bool,
+    //Max number of requests running at once
+    pub jobs: usize,
+    pub client: ClientConfig,
+    //Origin the data is requested from instead of the post url's one, ex. http://127.0.0.1:8080
+    pub host: Option<String>,
+    //Render saved data instead of requesting it, url is then the path of the data (see offline::load)
+    pub offline: bool,
+    //Continue the 'more' elements crawl saved by an interrupted run of the same url
+    pub resume: bool,
+    //Where to save the comment count reconciliation report (json), if anywhere
+    pub report_path: Option<String>,
+    //Archive every url listed in a file, url is then the path of the file (see batch::run)
+    pub batch: bool,
+    //Set if the url is a subreddit, every post of the listing gets archived
+    pub listing: Option<Listing>,
+    //Set if the url is a user, their submissions and comments get archived
+    pub profile: Option<Profile>,
+    //Number of parent comments archived above the comment of a comment permalink,
+    //overrides the ?context= of the url
+    pub context: Option<usize>,
+    //Set by `srp watch`, the post gets archived again every interval
+    pub watch: Option<Watch>,
+    //Set by `srp diff`, the archive in url gets compared with an older one
+    pub diff: Option<Diff>,
+}
+
+//Number of jobs used when --jobs isn't specified
+pub fn default_jobs() -> usize {
+    std::thread::available_parallelism().map_or(1, std::num::NonZeroUsize::get)
 }
 
 #[derive(Eq, PartialEq, Debug, Clone, Copy, Default)]
@@ -36,6 +80,8 @@ pub enum ElementSort {
     Comments(bool),   //Ascending or not
     Date(bool),       //Ascending or not
     EditedDate(bool), //Ascending or not
+    Awards(bool),     //Ascending or not
+    Controversial,    //Controversial elements first
 }
 
 #[derive(Eq, PartialEq, Debug, Clone)]
@@ -44,12 +90,12 @@ pub enum ElementFilterOp {
     EqString(String),
     NotEqString(String),
 
-    Eq(usize),
-    NotEq(usize),
-    Grater(usize),
-    GraterEq(usize),
-    Less(usize),
-    LessEq(usize),
+    Eq(i64),
+    NotEq(i64),
+    Grater(i64),
+    GraterEq(i64),
+    Less(i64),
+    LessEq(i64),
 }
 
 #[derive(Eq, PartialEq, Debug, Clone)]
@@ -59,6 +105,48 @@ pub enum ElementFilter {
     Comments(ElementFilterOp),
     Author(ElementFilterOp),
     Edited(bool),
+    Flair(ElementFilterOp),
+    Subreddit(ElementFilterOp),
+    Submitter(bool),
+    Stickied(bool),
+    Distinguished(bool),
+    Controversial(bool),
+    Deleted(bool), //Deleted or removed, see model::State
+    State(ElementFilterOp),
+}
+
+//Defaults used when srp is used as a library, url and base_url are left empty
+impl Default for CLI {
+    fn default() -> Self {
+        Self {
+            url: String::new(),
+            base_url: String::new(),
+            save_to_file: true,
+            save_path: String::from("output.txt"),
+            format: Format::Default,
+            dates: DateFormat::default(),
+            max_comments: usize::MAX,
+            sort_style: ElementSort::Default,
+            filter: ElementFilter::Default,
+            save_tmp_files: false,
+            verbosity: Verbosity::default(),
+            req_more_elements: true,
+            delete_tmp: false,
+            print_timestamps: false,
+            jobs: default_jobs(),
+            client: ClientConfig::default(),
+            host: None,
+            offline: false,
+            resume: false,
+            report_path: None,
+            batch: false,
+            listing: None,
+            profile: None,
+            context: None,
+            watch: None,
+            diff: None,
+        }
+    }
 }
 
 #[allow(dead_code)]
@@ -71,9 +159,23 @@ impl CLI {
             style("ERROR").bold().red(),
             style(err).red().bold()
         );
-        exit(0);
+        exit(1);
+    }
+
+    //Print the error and exit with it's exit code (see SrpError::exit_code)
+    pub fn exit_with_error(&self, err: &SrpError) -> ! {
+        println!(
+            "{}[{}::{}] {}",
+            crate::utils::get_timestamp(self.print_timestamps),
+            style("CLI").bold(),
+            style("ERROR").bold().red(),
+            style(err).red().bold()
+        );
+        exit(err.exit_code());
     }
 
+    //Used when parsing the arguments fails (there's no cli to take the settings from),
+    //so it exits with the invalid usage code
     pub fn print_err_no_timestamp<T: Display>(err: T) -> ! {
         println!(
             "[{}::{}] {}",
@@ -81,7 +183,7 @@ impl CLI {
             style("ERROR").bold().red(),
             style(err).red().bold()
         );
-        exit(0);
+        exit(SrpError::Validation(String::new()).exit_code());
     }
 
     pub fn print_info_no_timestamp<T: Display>(info: T) {
@@ -149,7 +251,8 @@ impl CLI {
         println!(" {} {}", style(arg).yellow().bold(), style(desc).blue());
     }
 
-    fn help(invalid_usage: bool) {
+    //Print the usage, the binary exits after it
+    pub fn help(invalid_usage: bool) {
         println!("{}", style("Usage:").green().bold());
         println!(
             "{} {} {}",
@@ -157,6 +260,18 @@ impl CLI {
             style("<arguments>").yellow().bold(),
             style("<url>").magenta().bold()
         );
+        println!(
+            "{} {} {}",
+            style("srp watch").green(),
+            style("<arguments>").yellow().bold(),
+            style("<url>").magenta().bold()
+        );
+        println!(
+            "{} {} {}",
+            style("srp diff").green(),
+            style("<arguments>").yellow().bold(),
+            style("<old.json> <new.json>").magenta().bold()
+        );
         println!("{}", style("Valid arguments:").green().bold());
 
         Self::print_arg("-h/--help", "display this help");
@@ -174,7 +289,95 @@ impl CLI {
         Self::print_arg("--save-tmp", "allow saving temp files (raw json data)");
         Self::print_arg("--delete-tmp", "delete temp files folder");
         Self::print_arg("--timestamps", "show timestamps");
+        Self::print_arg(
+            "-j/--jobs",
+            "set the max amount of requests running at once (nr of cpus by default)",
+        );
         Self::print_arg("-f/--format", "set the format (not case sensitive)");
+        Self::print_arg(
+            "--timezone",
+            "timezone of the dates: local (default), utc, an offset (+02:00) or a name (Europe/Warsaw)",
+        );
+        Self::print_arg(
+            "--date-format",
+            "strftime format of the dates (%Y-%m-%d %H:%M by default)",
+        );
+        Self::print_arg(
+            "--listing",
+            "for subreddit urls, the listing: hot (default), new, top, rising, controversial",
+        );
+        Self::print_arg(
+            "--time",
+            "for subreddit urls, the time range of top/controversial: hour, day, week, month, year, all",
+        );
+        Self::print_arg(
+            "--limit",
+            "for subreddit urls, how many posts to archive (25 by default), for user urls, how many submissions and comments (all by default)",
+        );
+        Self::print_arg(
+            "--context",
+            "for comment permalinks, how many parent comments to archive above the comment (overrides ?context=)",
+        );
+        Self::print_arg(
+            "--interval",
+            "watch: time between two rounds, ex. 90s, 10m, 2h (10m by default)",
+        );
+        Self::print_arg(
+            "--deadline",
+            "watch: stop after this much time, ex. 12h (no deadline by default)",
+        );
+        Self::print_arg(
+            "--quiet",
+            "watch: stop after this many rounds without new, edited or deleted comments (6 by default)",
+        );
+        Self::print_arg(
+            "--changelog",
+            "watch: file the changes of every round are appended to (changelog.txt by default)",
+        );
+        Self::print_arg(
+            "--batch",
+            "archive every url listed in a file (one per line, # for comments) or - for stdin, given in place of the url",
+        );
+        Self::print_arg(
+            "--report",
+            "save a json report explaining the difference between reported and fetched comments",
+        );
+        Self::print_arg(
+            "--resume",
+            "continue an interrupted run of the same url from its checkpoint (tmp/checkpoint/<post id>)",
+        );
+        Self::print_arg(
+            "--offline",
+            "render saved data instead of a url: a --save-tmp directory, a raw json file or - for stdin",
+        );
+        Self::print_arg(
+            "--host",
+            "request the data from this origin instead of reddit, ex. old.reddit.com, http://127.0.0.1:8080",
+        );
+        Self::print_arg("--user-agent", "set the user agent");
+        Self::print_arg(
+            "--timeout",
+            "set the request timeout in seconds (0 for none, 60 by default)",
+        );
+        Self::print_arg(
+            "--connect-timeout",
+            "set the connect timeout in seconds (0 for none, 30 by default)",
+        );
+        Self::print_arg("--no-gzip", "don't accept gzip compressed responses");
+        Self::print_arg("--no-brotli", "don't accept brotli compressed responses");
+        Self::print_arg("--http2", "use HTTP/2 without negotiating it");
+        Self::print_arg(
+            "--proxy",
+            "send requests through a proxy (http://, https://, socks5://, socks5h://)",
+        );
+        Self::print_arg(
+            "-H/--header",
+            "add a header to every request, \"Name: value\" (multiple allowed)",
+        );
+        Self::print_arg(
+            "--cookie",
+            "add a cookie to every request, name=value (multiple allowed)",
+        );
 
         let padding = '\t';
         println!(" {}", style("Valid formats:").green().bold());
@@ -196,6 +399,9 @@ impl CLI {
         println!("{padding}{}", style("old").yellow());
         println!("{padding}{}", style("edited").yellow());
         println!("{padding}{}", style("edited-asc").yellow());
+        println!("{padding}{}", style("awards").yellow());
+        println!("{padding}{}", style("awards-asc").yellow());
+        println!("{padding}{}", style("controversial").yellow());
 
         Self::print_arg(
             "--filter",
@@ -213,49 +419,51 @@ impl CLI {
             "{padding}{}",
             style("comments > >= == < <= != [nr]").yellow()
         );
-        println!("{padding}{}", style("edited [bool]").yellow());
-        println!("{padding}{}", style("author == != [value]").yellow());
+        println!(
+            "{padding}{}",
+            style("edited/op/stickied/distinguished/controversial/deleted [bool]").yellow()
+        );
+        println!(
+            "{padding}{}",
+            style("author/flair/subreddit == != [value]").yellow()
+        );
+        println!(
+            "{padding}{}",
+            style("state == != live/deleted/removed/author_deleted").yellow()
+        );
+
+        println!("{}", style("Exit codes:").green().bold());
+        println!("{padding}{}", style("0 success").yellow());
+        println!("{padding}{}", style("1 other error").yellow());
+        println!("{padding}{}", style("2 invalid usage/url").yellow());
+        println!("{padding}{}", style("3 network error").yellow());
+        println!("{padding}{}", style("4 http error status").yellow());
+        println!("{padding}{}", style("5 json parse error").yellow());
+        println!("{padding}{}", style("6 filesystem error").yellow());
 
         if invalid_usage {
             println!("{}", style("Invalid usage!").bold().red());
         }
-        if !cfg!(test) {
-            std::process::exit(0);
-        }
     }
 
-    pub fn parse_format(format: &str) -> String {
-        let mut save_path = String::from("output.txt");
+    //True if the arguments ask for the help (-h/--help), CLI::new doesn't handle it
+    pub fn is_help(args: &[String]) -> bool {
+        args.iter().skip(1).any(|o| o == "-h" || o == "--help")
+    }
 
-        match format.to_lowercase().trim() {
-            "default" | "d" => {
-                unsafe {
-                    crate::element::FORMAT = crate::element::Format::Default;
-                }
-                save_path = String::from("output.txt");
-            }
-            "html" | "h" => {
-                unsafe {
-                    crate::element::FORMAT = crate::element::Format::HTML;
-                }
-                save_path = String::from("output.html");
-            }
-            "json" | "j" => {
-                unsafe {
-                    crate::element::FORMAT = crate::element::Format::JSON;
-                }
-                save_path = String::from("output.json");
-            }
+    pub fn parse_format(format_: &str) -> Result<(Format, String), SrpError> {
+        Ok(match format_.to_lowercase().trim() {
+            "default" | "d" => (Format::Default, String::from("output.txt")),
+            "html" | "h" => (Format::HTML, String::from("output.html")),
+            "json" | "j" => (Format::JSON, String::from("output.json")),
             _ => {
-                println!("Invalid format: {format}");
-                Self::help(true);
+                return Err(SrpError::Validation(format!("Invalid format: {format_}")));
             }
-        }
-        save_path
+        })
     }
 
-    pub fn parse_sort_style(sort_style_: &str) -> ElementSort {
-        match sort_style_.to_lowercase().trim() {
+    pub fn parse_sort_style(sort_style_: &str) -> Result<ElementSort, SrpError> {
+        Ok(match sort_style_.to_lowercase().trim() {
             "default" => ElementSort::Default,
             "rand" | "random" => ElementSort::Rand,
             "upvotes" | "ups" => ElementSort::Upvotes(false),
@@ -266,167 +474,166 @@ impl CLI {
             "old" => ElementSort::Date(true),
             "edited" => ElementSort::EditedDate(false),
             "edited-asc" => ElementSort::EditedDate(true),
+            "awards" => ElementSort::Awards(false),
+            "awards-asc" => ElementSort::Awards(true),
+            "controversial" => ElementSort::Controversial,
             _ => {
-                println!("Invalid sort option: {sort_style_}");
-                Self::help(true);
-                ElementSort::Default
+                return Err(SrpError::Validation(format!(
+                    "Invalid sort option: {sort_style_}"
+                )));
             }
-        }
+        })
     }
 
     pub fn parse_filter_style(
         filter_: &String,
         operator: Option<&String>,
         value: Option<&String>,
-    ) -> Result<(u32, ElementFilter), String> {
-        let mut filter = ElementFilter::Default;
+    ) -> Result<(u32, ElementFilter), SrpError> {
+        let filter;
         let mut skip_count = 0;
         match filter_.to_lowercase().trim() {
             "ups" | "upvotes" => {
-                let Some(value) = value else { return Err("Failed to get filter style value".to_owned()) };
-                let Some(operator) = operator else { return Err("Failed to get filter style operator".to_owned()) };
+                let Some(value) = value else {
+                    return Err(SrpError::Validation(
+                        "Failed to get filter style value".to_owned(),
+                    ));
+                };
+                let Some(operator) = operator else {
+                    return Err(SrpError::Validation(
+                        "Failed to get filter style operator".to_owned(),
+                    ));
+                };
                 skip_count += 2;
-                match operator.as_str() {
-                    ">" => match value.parse::<usize>() {
-                        Ok(o) => {
-                            filter = ElementFilter::Upvotes(ElementFilterOp::Grater(o));
-                        }
-                        Err(_) => {
-                            println!("Invalid argument in filter: {filter_}");
-                        }
-                    },
-                    ">=" => match value.parse::<usize>() {
-                        Ok(o) => {
-                            filter = ElementFilter::Upvotes(ElementFilterOp::GraterEq(o));
-                        }
-                        Err(_) => {
-                            println!("Invalid argument in filter: {filter_}");
-                        }
-                    },
-                    "==" => match value.parse::<usize>() {
-                        Ok(o) => {
-                            filter = ElementFilter::Upvotes(ElementFilterOp::Eq(o));
-                        }
-                        Err(_) => {
-                            println!("Invalid argument in filter: {filter_}");
-                        }
-                    },
-                    "!=" => match value.parse::<usize>() {
-                        Ok(o) => {
-                            filter = ElementFilter::Upvotes(ElementFilterOp::NotEq(o));
-                        }
-                        Err(_) => {
-                            println!("Invalid argument in filter: {filter_}");
-                        }
-                    },
-                    "<" => match value.parse::<usize>() {
-                        Ok(o) => {
-                            filter = ElementFilter::Upvotes(ElementFilterOp::Less(o));
-                        }
-                        Err(_) => {
-                            println!("Invalid argument in filter: {filter_}");
-                        }
-                    },
-                    "<=" => match value.parse::<usize>() {
-                        Ok(o) => {
-                            filter = ElementFilter::Upvotes(ElementFilterOp::LessEq(o));
-                        }
-                        Err(_) => {
-                            println!("Invalid argument in filter: {filter_}");
-                        }
-                    },
-                    _ => println!("Invalid argument in filter: {filter_}"),
-                }
+                filter = ElementFilter::Upvotes(Self::parse_filter_op(filter_, operator, value)?);
             }
             "comments" => {
-                let Some(value) = value else { return Err("Failed to get filter style value".to_owned()) };
-                let Some(operator) = operator else { return Err("Failed to get filter style operator".to_owned()) };
+                let Some(value) = value else {
+                    return Err(SrpError::Validation(
+                        "Failed to get filter style value".to_owned(),
+                    ));
+                };
+                let Some(operator) = operator else {
+                    return Err(SrpError::Validation(
+                        "Failed to get filter style operator".to_owned(),
+                    ));
+                };
                 skip_count += 2;
-                match operator.as_str() {
-                    ">" => match value.parse::<usize>() {
-                        Ok(o) => {
-                            filter = ElementFilter::Comments(ElementFilterOp::Grater(o));
-                        }
-                        Err(_) => {
-                            println!("Invalid argument in filter: {filter_}");
-                        }
-                    },
-                    ">=" => match value.parse::<usize>() {
-                        Ok(o) => {
-                            filter = ElementFilter::Comments(ElementFilterOp::GraterEq(o));
-                        }
-                        Err(_) => {
-                            println!("Invalid argument in filter: {filter_}");
-                        }
-                    },
-                    "==" => match value.parse::<usize>() {
-                        Ok(o) => {
-                            filter = ElementFilter::Comments(ElementFilterOp::Eq(o));
-                        }
-                        Err(_) => {
-                            println!("Invalid argument in filter: {filter_}");
-                        }
-                    },
-                    "!=" => match value.parse::<usize>() {
-                        Ok(o) => {
-                            filter = ElementFilter::Comments(ElementFilterOp::NotEq(o));
-                        }
-                        Err(_) => {
-                            println!("Invalid argument in filter: {filter_}");
-                        }
-                    },
-                    "<" => match value.parse::<usize>() {
-                        Ok(o) => {
-                            filter = ElementFilter::Comments(ElementFilterOp::Less(o));
-                        }
-                        Err(_) => {
-                            println!("Invalid argument in filter: {filter_}");
-                        }
-                    },
-                    #[allow(clippy::unit_arg)]
-                    "<=" => value.parse::<usize>().map_or(
-                        println!("Invalid argument in filter: {filter_}"),
-                        |o| {
-                            filter = ElementFilter::Comments(ElementFilterOp::LessEq(o));
-                        },
-                    ),
-                    _ => Self::print_info_no_timestamp(format!(
-                        "Invalid argument in filter: {filter_}"
-                    )),
-                }
+                filter = ElementFilter::Comments(Self::parse_filter_op(filter_, operator, value)?);
             }
-            "edited" => {
-                let Some(operator) = operator else { return Err("Failed to get filter style operator".to_owned()) };
+            name @ ("edited" | "op" | "submitter" | "stickied" | "distinguished"
+            | "controversial" | "deleted") => {
+                let Some(operator) = operator else {
+                    return Err(SrpError::Validation(
+                        "Failed to get filter style operator".to_owned(),
+                    ));
+                };
                 skip_count += 1;
-                let operator = operator.to_lowercase();
-                if operator.trim() == "false" {
-                    filter = ElementFilter::Edited(false);
-                } else {
-                    filter = ElementFilter::Edited(true);
-                }
+                let value = operator.to_lowercase().trim() != "false";
+                filter = match name {
+                    "edited" => ElementFilter::Edited(value),
+                    "op" | "submitter" => ElementFilter::Submitter(value),
+                    "stickied" => ElementFilter::Stickied(value),
+                    "distinguished" => ElementFilter::Distinguished(value),
+                    "deleted" => ElementFilter::Deleted(value),
+                    _ => ElementFilter::Controversial(value),
+                };
             }
-            "author" => {
-                let Some(value) = value else { return Err("Failed to get filter style value".to_owned()) };
-                let Some(operator) = operator else { return Err("Failed to get filter style operator".to_owned()) };
+            name @ ("author" | "flair" | "subreddit" | "state") => {
+                let Some(value) = value else {
+                    return Err(SrpError::Validation(
+                        "Failed to get filter style value".to_owned(),
+                    ));
+                };
+                let Some(operator) = operator else {
+                    return Err(SrpError::Validation(
+                        "Failed to get filter style operator".to_owned(),
+                    ));
+                };
                 skip_count += 2;
                 let operator = operator.to_lowercase();
-                if operator.trim() == "==" {
-                    filter = ElementFilter::Author(ElementFilterOp::EqString(value.clone()));
+                let op = if operator.trim() == "==" {
+                    ElementFilterOp::EqString(value.clone())
                 } else if operator.trim() == "!=" {
-                    filter = ElementFilter::Author(ElementFilterOp::NotEqString(value.clone()));
+                    ElementFilterOp::NotEqString(value.clone())
                 } else {
-                    println!("Invalid operator in filter: {operator}");
-                }
+                    return Err(SrpError::Validation(format!(
+                        "Invalid operator in filter: {operator}"
+                    )));
+                };
+                filter = match name {
+                    "author" => ElementFilter::Author(op),
+                    "flair" => ElementFilter::Flair(op),
+                    "state" => ElementFilter::State(op),
+                    _ => ElementFilter::Subreddit(op),
+                };
+            }
+            _ => {
+                return Err(SrpError::Validation(format!(
+                    "Invalid argument in filter: {filter_}"
+                )))
             }
-            _ => Self::print_info_no_timestamp(format!("Invalid argument in filter: {filter_}")),
         };
         Ok((skip_count, filter))
     }
 
-    pub fn new(args: &[String]) -> Self {
+    //ex. >= 10
+    fn parse_filter_op(
+        filter_: &str,
+        operator: &str,
+        value: &str,
+    ) -> Result<ElementFilterOp, SrpError> {
+        let Ok(n) = value.parse::<i64>() else {
+            return Err(SrpError::Validation(format!(
+                "Invalid value in filter {filter_}: {value}"
+            )));
+        };
+        Ok(match operator {
+            ">" => ElementFilterOp::Grater(n),
+            ">=" => ElementFilterOp::GraterEq(n),
+            "==" => ElementFilterOp::Eq(n),
+            "!=" => ElementFilterOp::NotEq(n),
+            "<" => ElementFilterOp::Less(n),
+            "<=" => ElementFilterOp::LessEq(n),
+            _ => {
+                return Err(SrpError::Validation(format!(
+                    "Invalid operator in filter {filter_}: {operator}"
+                )))
+            }
+        })
+    }
+
+    //Parse the arguments, -h/--help is left to the binary (see CLI::is_help)
+    pub fn new(args: &[String]) -> Result<Self, SrpError> {
+        let missing = |arg: &str| SrpError::Validation(format!("Missing value of {arg}"));
+        //Subcommands come right after the binary name
+        let is_watch = args.get(1).is_some_and(|o| o == "watch");
+        let is_diff = args.get(1).is_some_and(|o| o == "diff");
+        let mut args: Vec<String> = if is_watch || is_diff {
+            args[..1].iter().chain(&args[2..]).cloned().collect()
+        } else {
+            args.to_vec()
+        };
+        //srp diff <arguments> <old> <new>, the new one takes the place of the url
+        let diff = if is_diff {
+            if args.len() < 3 {
+                return Err(SrpError::Validation(String::from(
+                    "srp diff needs an old and a new archive",
+                )));
+            }
+            Some(Diff {
+                old: args.remove(args.len() - 2),
+            })
+        } else {
+            None
+        };
+        let mut watch = Watch::default();
+        let mut watch_args = false;
         let mut url = String::new();
         let mut save_to_file = true;
         let mut save_path = String::from("output.txt");
+        let mut format = Format::Default;
+        let mut dates = DateFormat::default();
         let mut max_comments = usize::MAX;
         let mut sort_style = ElementSort::Default;
         let mut filter = ElementFilter::Default;
@@ -435,15 +642,22 @@ impl CLI {
         let mut req_more_elements = true;
         let mut delete_tmp = false;
         let mut print_timestamps = false;
+        let mut jobs = default_jobs();
+        let mut client = ClientConfig::default();
+        let mut host = None;
+        let mut offline = false;
+        let mut resume = false;
+        let mut report_path = None;
+        let mut batch = false;
+        let mut listing_sort = None;
+        let mut listing_time = None;
+        let mut listing_limit = None;
+        let mut context = None;
 
         if args.len() == 1 {
-            Self::help(true);
+            return Err(SrpError::Validation(String::from("Missing url")));
         } else if args.len() == 2 {
-            if args[1] == "-h" || args[1] == "--help" {
-                Self::help(false);
-            } else {
-                url = args[1].clone();
-            }
+            url = args[1].clone();
         } else {
             let mut skip_count = 0u32;
 
@@ -453,12 +667,10 @@ impl CLI {
                     continue;
                 }
                 match args[i].as_str() {
-                    "-h" | "--help" => {
-                        Self::help(false);
-                    }
+                    "-h" | "--help" => {}
                     "-s" | "--save" => {
                         if args.len() < i + 1 {
-                            Self::help(true);
+                            return Err(missing(&args[i]));
                         }
                         skip_count += 1;
                         save_path = args[i + 1].clone();
@@ -468,49 +680,44 @@ impl CLI {
                     }
                     "-f" | "--format" => {
                         if args.len() < i + 1 {
-                            Self::help(true);
+                            return Err(missing(&args[i]));
                         }
                         skip_count += 1;
-                        let format = args[i + 1].clone().to_lowercase();
-                        save_path = Self::parse_format(format.as_str());
+                        let format_ = args[i + 1].clone().to_lowercase();
+                        (format, save_path) = Self::parse_format(format_.as_str())?;
                     }
                     "-m" | "--max" => {
                         if args.len() < i + 1 {
-                            Self::help(true);
+                            return Err(missing(&args[i]));
                         }
                         skip_count += 1;
                         let max_comments_ = args[i + 1].clone();
-                        if let Ok(o) = max_comments_.parse::<usize>() {
-                            max_comments = std::cmp::max(o, 2);
-                        } else {
-                            println!("Invalid format: {}", args[i + 1]);
-                            Self::help(true);
-                        }
+                        let Ok(o) = max_comments_.parse::<usize>() else {
+                            return Err(SrpError::Validation(format!(
+                                "Invalid max: {}",
+                                args[i + 1]
+                            )));
+                        };
+                        max_comments = std::cmp::max(o, 2);
                     }
                     "--sort" => {
                         if args.len() < i + 1 {
-                            Self::help(true);
+                            return Err(missing(&args[i]));
                         }
                         skip_count += 1;
                         let sort_style_ = args[i + 1].clone().trim().to_lowercase();
-                        sort_style = Self::parse_sort_style(sort_style_.as_str());
+                        sort_style = Self::parse_sort_style(sort_style_.as_str())?;
                     }
                     "--filter" => {
                         if args.len() < i + 1 {
-                            Self::help(true);
+                            return Err(missing(&args[i]));
                         }
                         skip_count += 1;
                         let Some(filter_) = args.get(i + 1) else {
-                            Self::print_err_no_timestamp("Failed to get --filter filter")
-                        };
-                        let (skip_count_inc, filter_) = match Self::parse_filter_style(
-                            filter_,
-                            args.get(i + 2),
-                            args.get(i + 3),
-                        ) {
-                            Ok(o) => o,
-                            Err(e) => Self::print_err_no_timestamp(e),
+                            return Err(missing(&args[i]));
                         };
+                        let (skip_count_inc, filter_) =
+                            Self::parse_filter_style(filter_, args.get(i + 2), args.get(i + 3))?;
                         filter = filter_;
                         skip_count += skip_count_inc;
                     }
@@ -522,7 +729,7 @@ impl CLI {
                     }
                     "-v" | "--verbosity" => {
                         if args.len() < i + 1 {
-                            Self::help(true);
+                            return Err(missing(&args[i]));
                         }
                         skip_count += 1;
                         let v = args[i + 1].clone().to_lowercase();
@@ -537,18 +744,226 @@ impl CLI {
                                 verbosity = Verbosity::Low;
                             }
                             _ => {
-                                println!("Invalid verbosity {v}");
+                                return Err(SrpError::Validation(format!(
+                                    "Invalid verbosity: {v}"
+                                )));
+                            }
+                        }
+                    }
+                    "--listing" => {
+                        if args.len() < i + 1 {
+                            return Err(missing(&args[i]));
+                        }
+                        skip_count += 1;
+                        match ListingSort::parse(&args[i + 1]) {
+                            Some(o) => listing_sort = Some(o),
+                            None => {
+                                return Err(SrpError::Validation(format!(
+                                    "Invalid listing: {}",
+                                    args[i + 1]
+                                )))
+                            }
+                        }
+                    }
+                    "--time" => {
+                        if args.len() < i + 1 {
+                            return Err(missing(&args[i]));
+                        }
+                        skip_count += 1;
+                        let time = args[i + 1].trim().to_lowercase();
+                        if !TIME_RANGES.contains(&time.as_str()) {
+                            return Err(SrpError::Validation(format!(
+                                "Invalid time range: {time}"
+                            )));
+                        }
+                        listing_time = Some(time);
+                    }
+                    "--limit" => {
+                        if args.len() < i + 1 {
+                            return Err(missing(&args[i]));
+                        }
+                        skip_count += 1;
+                        match args[i + 1].parse::<usize>() {
+                            Ok(o) if o > 0 => listing_limit = Some(o),
+                            _ => {
+                                return Err(SrpError::Validation(format!(
+                                    "Invalid limit: {}",
+                                    args[i + 1]
+                                )))
+                            }
+                        }
+                    }
+                    "--context" => {
+                        if args.len() < i + 1 {
+                            return Err(missing(&args[i]));
+                        }
+                        skip_count += 1;
+                        match args[i + 1].parse::<usize>() {
+                            Ok(o) => context = Some(o),
+                            _ => {
+                                return Err(SrpError::Validation(format!(
+                                    "Invalid context: {}",
+                                    args[i + 1]
+                                )))
+                            }
+                        }
+                    }
+                    "--batch" => {
+                        batch = true;
+                    }
+                    "--report" => {
+                        if args.len() < i + 1 {
+                            return Err(missing(&args[i]));
+                        }
+                        skip_count += 1;
+                        report_path = Some(args[i + 1].clone());
+                    }
+                    "--resume" => {
+                        resume = true;
+                    }
+                    "--offline" => {
+                        offline = true;
+                    }
+                    "--interval" | "--deadline" => {
+                        if args.len() < i + 1 {
+                            return Err(missing(&args[i]));
+                        }
+                        skip_count += 1;
+                        watch_args = true;
+                        let duration = Self::parse_duration(&args[i + 1])?;
+                        if args[i] == "--interval" {
+                            watch.interval = duration;
+                        } else {
+                            watch.deadline = Some(duration);
+                        }
+                    }
+                    "--quiet" => {
+                        if args.len() < i + 1 {
+                            return Err(missing(&args[i]));
+                        }
+                        skip_count += 1;
+                        watch_args = true;
+                        match args[i + 1].parse::<usize>() {
+                            Ok(o) if o > 0 => watch.quiet_rounds = o,
+                            _ => {
+                                return Err(SrpError::Validation(format!(
+                                    "Invalid number of quiet rounds: {}",
+                                    args[i + 1]
+                                )))
                             }
                         }
                     }
+                    "--changelog" => {
+                        if args.len() < i + 1 {
+                            return Err(missing(&args[i]));
+                        }
+                        skip_count += 1;
+                        watch_args = true;
+                        watch.changelog = args[i + 1].clone();
+                    }
+                    "--timezone" => {
+                        if args.len() < i + 1 {
+                            return Err(missing(&args[i]));
+                        }
+                        skip_count += 1;
+                        dates.timezone = Timezone::parse(&args[i + 1])?;
+                    }
+                    "--date-format" => {
+                        if args.len() < i + 1 {
+                            return Err(missing(&args[i]));
+                        }
+                        skip_count += 1;
+                        DateFormat::validate_format(&args[i + 1])?;
+                        dates.format = args[i + 1].clone();
+                    }
+                    "--host" => {
+                        if args.len() < i + 1 {
+                            return Err(missing(&args[i]));
+                        }
+                        skip_count += 1;
+                        host = Some(Self::parse_host(&args[i + 1])?);
+                    }
+                    "--user-agent" => {
+                        if args.len() < i + 1 {
+                            return Err(missing(&args[i]));
+                        }
+                        skip_count += 1;
+                        client.user_agent = args[i + 1].clone();
+                    }
+                    "--timeout" | "--connect-timeout" => {
+                        if args.len() < i + 1 {
+                            return Err(missing(&args[i]));
+                        }
+                        skip_count += 1;
+                        let Ok(secs) = args[i + 1].parse::<u64>() else {
+                            return Err(SrpError::Validation(format!(
+                                "Invalid timeout: {}",
+                                args[i + 1]
+                            )));
+                        };
+                        let timeout = (secs != 0).then(|| std::time::Duration::from_secs(secs));
+                        if args[i] == "--timeout" {
+                            client.timeout = timeout;
+                        } else {
+                            client.connect_timeout = timeout;
+                        }
+                    }
+                    "--no-gzip" => {
+                        client.gzip = false;
+                    }
+                    "--no-brotli" => {
+                        client.brotli = false;
+                    }
+                    "--http2" => {
+                        client.http2_prior_knowledge = true;
+                    }
+                    "--proxy" => {
+                        if args.len() < i + 1 {
+                            return Err(missing(&args[i]));
+                        }
+                        skip_count += 1;
+                        client.proxy = Some(args[i + 1].clone());
+                    }
+                    "-H" | "--header" => {
+                        if args.len() < i + 1 {
+                            return Err(missing(&args[i]));
+                        }
+                        skip_count += 1;
+                        client
+                            .headers
+                            .push(ClientConfig::parse_header(&args[i + 1])?);
+                    }
+                    "--cookie" => {
+                        if args.len() < i + 1 {
+                            return Err(missing(&args[i]));
+                        }
+                        skip_count += 1;
+                        client.cookies.push(args[i + 1].clone());
+                    }
                     "--no-more-elements" => {
                         req_more_elements = false;
                     }
                     "--timestamps" => {
                         print_timestamps = true;
                     }
+                    "-j" | "--jobs" => {
+                        if args.len() < i + 1 {
+                            return Err(missing(&args[i]));
+                        }
+                        skip_count += 1;
+                        let Ok(o) = args[i + 1].parse::<usize>() else {
+                            return Err(SrpError::Validation(format!(
+                                "Invalid jobs: {}",
+                                args[i + 1]
+                            )));
+                        };
+                        jobs = std::cmp::max(o, 1);
+                    }
                     _ => {
-                        println!("Invalid argument: {}", args[i]);
+                        return Err(SrpError::Validation(format!(
+                            "Invalid argument: {}",
+                            args[i]
+                        )));
                     }
                 }
             }
@@ -558,12 +973,74 @@ impl CLI {
             );
         }
 
-        let (url, base_url) = Self::parse_url(url);
-        Self {
+        if offline && batch {
+            return Err(SrpError::Validation(String::from(
+                "--offline and --batch can't be used together",
+            )));
+        }
+        if watch_args && !is_watch {
+            return Err(SrpError::Validation(String::from(
+                "--interval, --deadline, --quiet and --changelog only work with srp watch",
+            )));
+        }
+        if is_watch && (offline || batch || resume) {
+            return Err(SrpError::Validation(String::from(
+                "srp watch can't be used with --offline, --batch or --resume",
+            )));
+        }
+        //The input is read from files
+        let local = offline || batch || is_diff;
+        let listing = if local {
+            None
+        } else {
+            Listing::from_url(&url).map(|mut o| {
+                o.sort = listing_sort.unwrap_or(o.sort);
+                o.time = listing_time.or(o.time);
+                o.limit = listing_limit.unwrap_or(o.limit);
+                o
+            })
+        };
+        let profile = if local {
+            None
+        } else {
+            Profile::from_url(&url).map(|mut o| {
+                o.limit = listing_limit.unwrap_or(o.limit);
+                o
+            })
+        };
+        if context.is_some()
+            && !batch
+            && RedditUrl::parse(&url, host.as_deref())
+                .is_ok_and(|o| !o.is_share() && o.comment().is_none())
+        {
+            return Err(SrpError::Validation(String::from(
+                "--context only works with comment permalinks",
+            )));
+        }
+        if is_watch && (listing.is_some() || profile.is_some()) {
+            return Err(SrpError::Validation(String::from(
+                "srp watch only works with post urls",
+            )));
+        }
+        let (url, base_url) = if local {
+            //The urls are read later
+            (url.trim().to_owned(), String::new())
+        } else if let Some(profile) = &profile {
+            let base_url = profile.base_url(host.as_deref().unwrap_or(REDDIT_ORIGIN));
+            (base_url.clone(), base_url)
+        } else if let Some(listing) = &listing {
+            let base_url = listing.base_url(host.as_deref().unwrap_or(REDDIT_ORIGIN));
+            (base_url.clone(), base_url)
+        } else {
+            Self::parse_url_with_host(url, host.as_deref())?
+        };
+        Ok(Self {
             url,
             base_url,
             save_to_file,
             save_path,
+            format,
+            dates,
             max_comments,
             sort_style,
             filter,
@@ -572,67 +1049,78 @@ impl CLI {
             req_more_elements,
             delete_tmp,
             print_timestamps,
-        }
+            jobs,
+            client,
+            host,
+            offline,
+            resume,
+            report_path,
+            batch,
+            listing,
+            profile,
+            context,
+            watch: is_watch.then_some(watch),
+            diff,
+        })
     }
 
-    //TODO: Make this return a result, for easier testing.
-    pub fn parse_url(mut url: String) -> (String, String) {
-        //If it's not a reddit url it's concidered invalid
-        if !url.contains("reddit.com/r/") {
-            println!("{}", style(format!("Invalid url: {url}")).red().bold());
-            Self::help(true);
-        }
-
-        url = url.replace('\'', "");
-        url = url.replace(' ', "");
-        url = url.replace('\n', "");
-        url = url.trim().to_owned();
-
-        let search_for = '?';
-
-        //Delete the query part
-        url = match url.rfind(search_for) {
-            Some(idx) => url[0..idx].to_string(),
-            _ => url,
-        };
-
-        let search_for = "https://";
+    //Returns the url of the post's json data, and the base url of the post
+    pub fn parse_url(url: String) -> Result<(String, String), SrpError> {
+        Self::parse_url_with_host(url, None)
+    }
 
-        //Delete check if starts with "https://", if not add it
-        let start_idx = search_for.len() - 1;
-        if !url.starts_with(search_for) {
-            url = search_for.to_owned() + &url;
-        }
-        //Look for ":", if found delete everything after it.
-        url = match url[start_idx..].rfind(':') {
-            Some(colon_idx) => url[0..colon_idx + start_idx].to_string(),
-            _ => url,
+    //Parse a duration, ex. 90s, 10m, 2h, 1d, plain numbers are seconds
+    pub fn parse_duration(duration: &str) -> Result<Duration, SrpError> {
+        let duration = duration.trim();
+        let invalid = || SrpError::Validation(format!("Invalid duration: {duration}"));
+        let (n, unit) = duration
+            .find(|o: char| !o.is_ascii_digit())
+            .map_or((duration, ""), |i| duration.split_at(i));
+        let n = n.parse::<u64>().map_err(|_| invalid())?;
+        let multiplier = match unit {
+            "" | "s" => 1,
+            "m" => 60,
+            "h" => 60 * 60,
+            "d" => 60 * 60 * 24,
+            _ => return Err(invalid()),
         };
-
-        //This url is now concidered a base url
-        let mut base_url = url.clone();
-
-        //If url doens't contain at least 3 / it's assumed to be invalid
-        if url.matches('/').count() < 3 {
-            println!("{}", style(format!("Invalid url: {url}")).red().bold());
-            Self::help(true);
+        //A zero interval would request the post in a loop
+        if n == 0 {
+            return Err(SrpError::Validation(format!(
+                "The duration must be more than 0: {duration}"
+            )));
         }
+        let secs = n.checked_mul(multiplier).ok_or_else(invalid)?;
+        Ok(Duration::from_secs(secs))
+    }
 
-        //If url ends with '/' delete it, if not add '/' to the base url
-        if url.ends_with('/') {
-            url = url[0..url.len() - 1].to_string();
+    //Parse the --host option, returns the origin without a trailing '/'
+    //Plain hosts get https://, ex. old.reddit.com -> https://old.reddit.com
+    pub fn parse_host(host: &str) -> Result<String, SrpError> {
+        let host = host.trim().trim_end_matches('/');
+        let host = if host.contains("://") {
+            host.to_owned()
         } else {
-            base_url += "/";
-        }
-
-        //Check if url ends with .json, if not add it
-        if !std::path::Path::new(&url)
-            .extension()
-            .map_or(false, |ext| ext.eq_ignore_ascii_case("json"))
+            "https://".to_owned() + host
+        };
+        let invalid = || SrpError::Validation(format!("Invalid host: {host}"));
+        let parsed = reqwest::Url::parse(&host).map_err(|_| invalid())?;
+        if !matches!(parsed.scheme(), "http" | "https")
+            || parsed.host_str().is_none()
+            || parsed.path() != "/"
+            || parsed.query().is_some()
         {
-            url += ".json";
+            return Err(invalid());
         }
+        Ok(host)
+    }
 
-        (url, base_url)
+    //Same as parse_url, but the returned urls point to host (see parse_host) instead of reddit.
+    //Urls already pointing to host are accepted too.
+    pub fn parse_url_with_host(
+        url: String,
+        host: Option<&str>,
+    ) -> Result<(String, String), SrpError> {
+        RedditUrl::parse(&url, host).map(|o| o.urls())
     }
 }
@@ -0,0 +1,109 @@
+use std::{sync::Arc, time::Duration};
+
+use reqwest::{
+    cookie::Jar,
+    header::{HeaderMap, HeaderName, HeaderValue},
+    Url,
+};
+
+use crate::error::SrpError;
+
+pub const USER_AGENT: &str =
+    "Mozilla/5.0 (X11; Linux x86_64; rv:109.0) Gecko/20100101 Firefox/114.0";
+
+//Configuration of the http client shared by every request of a run
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ClientConfig {
+    pub user_agent: String,
+    //Timeout of a whole request
+    pub timeout: Option<Duration>,
+    pub connect_timeout: Option<Duration>,
+    pub gzip: bool,
+    pub brotli: bool,
+    //Use HTTP/2 without negotiating it first
+    pub http2_prior_knowledge: bool,
+    //http://, https://, socks5:// or socks5h:// proxy url
+    pub proxy: Option<String>,
+    //Extra headers sent with every request (name, value)
+    pub headers: Vec<(String, String)>,
+    //Cookies sent with every request (name=value)
+    pub cookies: Vec<String>,
+}
+
+impl Default for ClientConfig {
+    fn default() -> Self {
+        Self {
+            user_agent: String::from(USER_AGENT),
+            timeout: Some(Duration::from_secs(60)),
+            connect_timeout: Some(Duration::from_secs(30)),
+            gzip: true,
+            brotli: true,
+            http2_prior_knowledge: false,
+            proxy: None,
+            headers: Vec::new(),
+            cookies: Vec::new(),
+        }
+    }
+}
+
+impl ClientConfig {
+    //Parse a "Name: value" header
+    pub fn parse_header(header: &str) -> Result<(String, String), SrpError> {
+        let Some((name, value)) = header.split_once(':') else {
+            return Err(SrpError::Validation(format!("Invalid header: {header}")));
+        };
+        let (name, value) = (name.trim(), value.trim());
+        if name.is_empty() {
+            return Err(SrpError::Validation(format!("Invalid header: {header}")));
+        }
+        Ok((name.to_owned(), value.to_owned()))
+    }
+
+    //Build the client, cookies are set for the origin of url
+    pub fn build(&self, url: &str) -> Result<reqwest::Client, SrpError> {
+        let mut builder = reqwest::Client::builder()
+            .user_agent(&self.user_agent)
+            .gzip(self.gzip)
+            .brotli(self.brotli)
+            .cookie_store(true);
+
+        if let Some(timeout) = self.timeout {
+            builder = builder.timeout(timeout);
+        }
+        if let Some(timeout) = self.connect_timeout {
+            builder = builder.connect_timeout(timeout);
+        }
+        if self.http2_prior_knowledge {
+            builder = builder.http2_prior_knowledge();
+        }
+        if let Some(proxy) = &self.proxy {
+            let proxy = reqwest::Proxy::all(proxy)
+                .map_err(|e| SrpError::Validation(format!("Invalid proxy {proxy}: {e}")))?;
+            builder = builder.proxy(proxy);
+        }
+
+        let mut headers = HeaderMap::new();
+        for (name, value) in &self.headers {
+            let name = HeaderName::from_bytes(name.as_bytes())
+                .map_err(|e| SrpError::Validation(format!("Invalid header name {name}: {e}")))?;
+            let value = HeaderValue::from_str(value)
+                .map_err(|e| SrpError::Validation(format!("Invalid header value {value}: {e}")))?;
+            headers.append(name, value);
+        }
+        builder = builder.default_headers(headers);
+
+        if !self.cookies.is_empty() {
+            let url = Url::parse(url)
+                .map_err(|e| SrpError::Validation(format!("Invalid url {url}: {e}")))?;
+            let jar = Jar::default();
+            for cookie in &self.cookies {
+                jar.add_cookie_str(cookie, &url);
+            }
+            builder = builder.cookie_provider(Arc::new(jar));
+        }
+
+        builder
+            .build()
+            .map_err(|e| SrpError::Network(format!("Failed to build client: {e}")))
+    }
+}
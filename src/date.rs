@@ -0,0 +1,134 @@
+use chrono::{
+    format::{Item, StrftimeItems},
+    DateTime, FixedOffset, Local, SecondsFormat, Utc,
+};
+
+use crate::error::SrpError;
+
+//Timezone dates are shown in
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Timezone {
+    //The timezone of the system
+    #[default]
+    Local,
+    Utc,
+    //ex. +02:00
+    Fixed(FixedOffset),
+    //ex. Europe/Warsaw
+    Named(chrono_tz::Tz),
+}
+
+impl Timezone {
+    //local, utc, an offset (+02:00, -0530, +2) or a tz database name
+    pub fn parse(value: &str) -> Result<Self, SrpError> {
+        let value = value.trim();
+        match value.to_lowercase().as_str() {
+            "local" => return Ok(Self::Local),
+            "utc" | "z" => return Ok(Self::Utc),
+            _ => {}
+        }
+        if let Some(o) = Self::parse_offset(value) {
+            return Ok(Self::Fixed(o));
+        }
+        value
+            .parse::<chrono_tz::Tz>()
+            .map(Self::Named)
+            .map_err(|_| SrpError::Validation(format!("Invalid timezone: {value}")))
+    }
+
+    fn parse_offset(value: &str) -> Option<FixedOffset> {
+        let (sign, rest) = match value.as_bytes().first()? {
+            b'+' => (1, &value[1..]),
+            b'-' => (-1, &value[1..]),
+            _ => return None,
+        };
+        let (hours, minutes) = match rest.split_once(':') {
+            Some(o) => o,
+            None if rest.len() == 4 => (rest.get(..2)?, rest.get(2..)?),
+            None => (rest, "0"),
+        };
+        //parse would take signs too, ex. +-2
+        let digits = |o: &str| !o.is_empty() && o.bytes().all(|o| o.is_ascii_digit());
+        if !digits(hours) || !digits(minutes) {
+            return None;
+        }
+        let hours = hours.parse::<i32>().ok()?;
+        let minutes = minutes.parse::<i32>().ok()?;
+        if minutes >= 60 {
+            return None;
+        }
+        FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60))
+    }
+}
+
+//How dates are shown in the Default and HTML outputs, JSON always uses ISO 8601 (see iso)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DateFormat {
+    pub timezone: Timezone,
+    //strftime format of the absolute dates
+    pub format: String,
+    //Relative dates are relative to this, None for the current time
+    pub now: Option<DateTime<Utc>>,
+}
+
+impl Default for DateFormat {
+    fn default() -> Self {
+        Self {
+            timezone: Timezone::default(),
+            format: String::from("%Y-%m-%d %H:%M"),
+            now: None,
+        }
+    }
+}
+
+impl DateFormat {
+    //Errors if the format has invalid specifiers, chrono would panic while formatting them
+    pub fn validate_format(format: &str) -> Result<(), SrpError> {
+        if StrftimeItems::new(format).any(|o| matches!(o, Item::Error)) {
+            return Err(SrpError::Validation(format!(
+                "Invalid date format: {format}"
+            )));
+        }
+        Ok(())
+    }
+
+    //The date in the timezone and format, ex. 2023-01-30 12:34
+    pub fn absolute(&self, date: &DateTime<Utc>) -> String {
+        let format = self.format.as_str();
+        match self.timezone {
+            Timezone::Local => date.with_timezone(&Local).format(format).to_string(),
+            Timezone::Utc => date.format(format).to_string(),
+            Timezone::Fixed(o) => date.with_timezone(&o).format(format).to_string(),
+            Timezone::Named(o) => date.with_timezone(&o).format(format).to_string(),
+        }
+    }
+
+    //ex. just now, 5m ago, 3h ago, 2d ago, 4mo ago, 1y ago
+    pub fn relative(&self, date: &DateTime<Utc>) -> String {
+        let now = self.now.unwrap_or_else(Utc::now);
+        let secs = (now - *date).num_seconds();
+        match secs {
+            ..=59 => String::from("just now"),
+            60..=3599 => format!("{}m ago", secs / 60),
+            3600..=86_399 => format!("{}h ago", secs / 3600),
+            86_400..=2_591_999 => format!("{}d ago", secs / 86_400),
+            2_592_000..=31_535_999 => format!("{}mo ago", secs / 2_592_000),
+            _ => format!("{}y ago", secs / 31_536_000),
+        }
+    }
+}
+
+//ISO 8601, ex. 2023-01-30T12:34:56Z
+pub fn iso(date: &DateTime<Utc>) -> String {
+    date.to_rfc3339_opts(SecondsFormat::AutoSi, true)
+}
+
+//Reddit's timestamps are seconds since the epoch, as floats
+pub fn from_timestamp(secs: f64) -> Option<DateTime<Utc>> {
+    if !secs.is_finite() {
+        return None;
+    }
+    let whole = secs.floor();
+    let nanos = ((secs - whole) * 1e9).round() as u32;
+    DateTime::from_timestamp(whole as i64, nanos.min(999_999_999))
+}
@@ -0,0 +1,206 @@
+use json::JsonValue;
+
+use crate::{
+    cli::CLI,
+    element::Format,
+    error::SrpError,
+    snapshot::{self, Change, Snapshot},
+    utils,
+};
+
+//Settings of `srp diff <old> <new>`, the path of the new archive is cli.url
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diff {
+    //Path of the old archive
+    pub old: String,
+}
+
+//A line of a text diff
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DiffLine {
+    Same(String),
+    Removed(String),
+    Added(String),
+}
+
+//Compare two json outputs of the same post, and write the changes in the format of the cli
+pub fn run(cli: &CLI, diff: &Diff) -> Result<(), SrpError> {
+    let old = load(&diff.old)?;
+    let new = load(&cli.url)?;
+    let changes = old.changes(&new);
+    cli.print_infom(format!(
+        "{} elements in {}, {} in {}, {} change{}",
+        old.len(),
+        diff.old,
+        new.len(),
+        cli.url,
+        changes.len(),
+        if changes.len() == 1 { "" } else { "s" }
+    ));
+
+    let title = format!("{} -> {}", diff.old, cli.url);
+    let content = render(&old, &new, &changes, cli.format, &title);
+    if cli.save_to_file {
+        std::fs::write(&cli.save_path, content).map_err(|e| {
+            SrpError::Filesystem(format!("Failed to write to {}: {e}", cli.save_path))
+        })?;
+        cli.print_info(format!("Writing to {}: success", cli.save_path));
+    } else {
+        print!("{content}");
+    }
+    Ok(())
+}
+
+//Load a json output of srp
+pub fn load(path: &str) -> Result<Snapshot, SrpError> {
+    let data = std::fs::read_to_string(path)
+        .map_err(|e| SrpError::Filesystem(format!("Failed to read {path}: {e}")))?;
+    Snapshot::from_output(&json::parse(&data)?)
+}
+
+//Line by line diff of two texts, through their longest common subsequence
+pub fn text_diff(old: &str, new: &str) -> Vec<DiffLine> {
+    let old = old.lines().collect::<Vec<_>>();
+    let new = new.lines().collect::<Vec<_>>();
+    //lcs[i][j] is the length of the lcs of old[i..] and new[j..]
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut out = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            out.push(DiffLine::Same(old[i].to_owned()));
+            i += 1;
+            j += 1;
+        } else if j < new.len() && (i == old.len() || lcs[i][j + 1] > lcs[i + 1][j]) {
+            out.push(DiffLine::Added(new[j].to_owned()));
+            j += 1;
+        } else {
+            out.push(DiffLine::Removed(old[i].to_owned()));
+            i += 1;
+        }
+    }
+    out
+}
+
+//Render the changes, title is the title/source of the document
+pub fn render(
+    old: &Snapshot,
+    new: &Snapshot,
+    changes: &[Change],
+    format: Format,
+    title: &str,
+) -> String {
+    match format {
+        Format::Default => {
+            let mut content = format!("Diff: {title}\n{}\n", snapshot::summary(changes));
+            for change in changes {
+                content += &format!("\n{change}\n");
+                for line in details(old, new, change) {
+                    content += &match line {
+                        DiffLine::Same(o) => format!("      {o}\n"),
+                        DiffLine::Removed(o) => format!("    - {o}\n"),
+                        DiffLine::Added(o) => format!("    + {o}\n"),
+                    };
+                }
+            }
+            content
+        }
+        Format::HTML => {
+            let mut content =
+                include_str!("html_file.html").replace("{title}", &utils::escape_html(title));
+            content += &format!(
+                "\n\t<h3>{}</h3>",
+                utils::escape_html(&snapshot::summary(changes))
+            );
+            for change in changes {
+                let lines = details(old, new, change)
+                    .into_iter()
+                    .map(|o| match o {
+                        DiffLine::Same(o) => format!("<span>{}</span>", utils::escape_html(&o)),
+                        DiffLine::Removed(o) => format!("<del>{}</del>", utils::escape_html(&o)),
+                        DiffLine::Added(o) => format!("<ins>{}</ins>", utils::escape_html(&o)),
+                    })
+                    .collect::<Vec<_>>();
+                let lines = if lines.is_empty() {
+                    String::new()
+                } else {
+                    format!("\n\t\t<pre>{}</pre>", lines.join("\n"))
+                };
+                content += &format!(
+                    "\n\t<div class=\"element\">\n\t\t<h4>{}</h4>{lines}\n\t</div>",
+                    utils::escape_html(&change.to_string())
+                );
+            }
+            content + "\n\t</div>\n</body>\n</html>"
+        }
+        Format::JSON => {
+            let changes = changes
+                .iter()
+                .map(|o| to_json(old, new, o))
+                .collect::<Vec<_>>();
+            json::object! {
+                title: title,
+                changes: changes,
+            }
+            .pretty(4)
+        }
+    }
+}
+
+//The text of new and deleted elements, the text diff of edited ones
+fn details(old: &Snapshot, new: &Snapshot, change: &Change) -> Vec<DiffLine> {
+    let lines = |data: &str, f: fn(String) -> DiffLine| {
+        data.lines().map(|o| f(o.to_owned())).collect::<Vec<_>>()
+    };
+    match change {
+        Change::Added { id, .. } => new
+            .get(id)
+            .map(|o| lines(&o.data, DiffLine::Added))
+            .unwrap_or_default(),
+        Change::Edited { old, new, .. } => text_diff(old, new),
+        Change::Deleted { id, .. } | Change::Missing { id, .. } => old
+            .get(id)
+            .map(|o| lines(&o.data, DiffLine::Removed))
+            .unwrap_or_default(),
+        Change::Score { .. } => Vec::new(),
+    }
+}
+
+fn to_json(old: &Snapshot, new: &Snapshot, change: &Change) -> JsonValue {
+    let mut out = json::object! {
+        type: change.kind(),
+        id: change.id(),
+        author: change.author(),
+    };
+    match change {
+        Change::Added { parent_id, .. } => out["parent_id"] = parent_id.as_str().into(),
+        Change::Score { old, new, .. } => {
+            out["old"] = (*old).into();
+            out["new"] = (*new).into();
+        }
+        _ => {}
+    }
+    let lines = details(old, new, change);
+    if !lines.is_empty() {
+        out["diff"] = lines
+            .into_iter()
+            .map(|o| match o {
+                DiffLine::Same(o) => format!("  {o}"),
+                DiffLine::Removed(o) => format!("- {o}"),
+                DiffLine::Added(o) => format!("+ {o}"),
+            })
+            .collect::<Vec<_>>()
+            .into();
+    }
+    out
+}
@@ -1,18 +1,19 @@
 extern crate json;
 extern crate reqwest;
 
-use std::{
-    str::FromStr,
-    sync::{Arc, Mutex},
-    thread,
-    time::SystemTime,
-};
-
-use console::style;
+use chrono::{DateTime, Utc};
 use json::JsonValue;
-
-use crate::{cli::Verbosity, convert_time, request, utils::get_timestamp};
-use std::io::Write;
+use serde::Deserialize;
+
+use crate::{
+    date::{self, DateFormat},
+    error::SrpError,
+    markdown,
+    media::Media,
+    model::{Edited, Kind, Metadata, State, Thing},
+    session::FetchSession,
+    utils::escape_html,
+};
 
 #[derive(Debug)]
 pub struct Empty;
@@ -25,44 +26,32 @@ pub enum Format {
     JSON,
 }
 
-pub static mut NUM_COMMENTS: usize = 0;
-pub static mut ELEMENTS_COUNT: usize = 1;
-pub static mut MORE_ELEMENTS_COUNT: usize = 0;
-pub static mut MORE_ELEMENTS: Vec<String> = Vec::new();
-pub static mut FORMAT: Format = Format::Default;
-
-macro_rules! get_safe {
-    ($var:ident) => {
-        unsafe { $var.clone() }
-    };
-}
-
-//var,field name, def value
-macro_rules! get_data_wrapper {
-    ($var:ident,$name:expr,$def_value:expr) => {
-        match Element::get_data($var, stringify!($name)) {
-            Ok(o) => o,
-            _ => $def_value,
-        }
-    };
-}
-
 //TODO: add better debug formatting
 #[derive(Clone, Debug)]
 pub struct Element {
     pub author: String,
     data: String,
-    kind: String,
+    //Title of posts, empty for comments
+    title: String,
+    //Markdown of the selftext or the body
+    body: String,
+    kind: Kind,
     url: String, //url_overridden_by_dest
-    pub ups: usize,
+    pub ups: i64,
     pub children: Vec<Element>,
-    depth: String,
+    pub depth: usize,
     permalink: String,
     pub id: String,
     pub parent_id: String,
     over_18: bool,
-    pub created: usize,
-    pub edited: usize,
+    pub created: Option<DateTime<Utc>>,
+    pub edited: Edited,
+    //The comment a comment permalink points to, it's marked in the output
+    pub focused: bool,
+    pub state: State,
+    pub meta: Metadata,
+    //Gallery, poll, video or crosspost of posts
+    pub media: Option<Media>,
 }
 
 #[cfg(test)]
@@ -71,17 +60,23 @@ impl Default for Element {
         Self {
             author: String::from("DEF_AUTHOR"),
             data: String::from("DEF_DATA"),
-            kind: String::from("DEF_KIND"),
+            title: String::new(),
+            body: String::from("DEF_DATA"),
+            kind: Kind::default(),
             url: String::from("DEF_URL"),
             ups: 0,
             children: Vec::new(),
-            depth: String::from("DEF_DEPTH"),
+            depth: 0,
             permalink: String::from("DEF_PERMALINK"),
             id: String::from("DEF_ID"),
             parent_id: String::from("DEF_PARENT_ID"),
             over_18: Default::default(),
             created: Default::default(),
             edited: Default::default(),
+            focused: false,
+            state: State::default(),
+            meta: Metadata::default(),
+            media: None,
         }
     }
 }
@@ -93,35 +88,76 @@ impl PartialEq for Element {
     }
 }
 
-impl std::fmt::Display for Element {
+//An element paired with the format it should be displayed in
+#[derive(Clone, Copy)]
+pub struct FormattedElement<'a> {
+    element: &'a Element,
+    format: Format,
+    //DateFormat::default() if None
+    dates: Option<&'a DateFormat>,
+}
+
+impl<'a> FormattedElement<'a> {
+    pub const fn with_dates(mut self, dates: &'a DateFormat) -> Self {
+        self.dates = Some(dates);
+        self
+    }
+}
+
+impl std::fmt::Display for FormattedElement<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        if self.data.is_empty() || self.author.is_empty() {
-            return std::fmt::Result::Err(std::fmt::Error::default());
-        }
-        return match get_safe!(FORMAT) {
+        let elem = self.element;
+        let default_dates;
+        let dates = if let Some(o) = self.dates {
+            o
+        } else {
+            default_dates = DateFormat::default();
+            &default_dates
+        };
+        match self.format {
             Format::Default => {
-                let children = self
+                let children = elem
                     .children
                     .iter()
-                    .map(|x| format!("{x}"))
+                    .map(|x| {
+                        let child = FormattedElement {
+                            element: x,
+                            ..*self
+                        };
+                        format!("{child}")
+                    })
                     .collect::<String>();
 
                 let indent_char = " ";
                 //let secondary_indent_char = " ";
-                let indent = indent_char.repeat(usize::from_str(&self.depth).unwrap_or(0));
-                let ups_indent = indent_char.repeat(self.ups.to_string().len());
+                let indent = indent_char.repeat(elem.depth);
+                let ups_indent = indent_char.repeat(elem.ups.to_string().len());
+                let mut author = elem
+                    .tags()
+                    .iter()
+                    .fold(elem.author().to_owned(), |acc, o| format!("{acc} [{o}]"));
+                if let Some(o) = elem.date_label(dates) {
+                    author += &format!(" ({o})");
+                }
+                let mut text = elem.data.clone();
+                if let Some(o) = &elem.media {
+                    for line in o.to_text(dates) {
+                        text += "\n";
+                        text += &line;
+                    }
+                }
                 //TODO: make this more readable
                 f.write_fmt(format_args!(
                     "{}{} {} {}: {}\n{}",
                     indent,
-                    self.depth,
-                    self.ups,
-                    self.author,
-                    self.data.replace(
+                    elem.depth,
+                    elem.ups,
+                    author,
+                    text.replace(
                         '\n',
                         &(String::from('\n')
                             + &(indent.to_string()
-                                + &indent_char.repeat(self.author.len() + 4)
+                                + &indent_char.repeat(author.len() + 4)
                                 + &ups_indent
                                 + " ")) //.replace(indent_char, secondary_indent_char))
                     ),
@@ -129,32 +165,85 @@ impl std::fmt::Display for Element {
                 ))
             }
             Format::HTML => {
-                let children = self
+                let children = elem
                     .children
                     .iter()
-                    .map(|x| format!("{x}"))
+                    .map(|x| {
+                        let child = FormattedElement {
+                            element: x,
+                            ..*self
+                        };
+                        format!("{child}")
+                    })
                     .collect::<String>();
                 let indent_char = " ";
-                let indent = "\t".to_owned()
-                    + &indent_char.repeat(usize::from_str(&self.depth).unwrap_or(0));
-                let url = if self.url.is_empty() {
+                let indent = "\t".to_owned() + &indent_char.repeat(elem.depth);
+                let url = if elem.url.is_empty() {
                     String::new()
                 } else {
-                    format!("<a href=\"{}\">{}</a>", self.url, self.url)
+                    format!(
+                        "<a href=\"{}\">{}</a>",
+                        escape_html(&markdown::safe_url(&elem.url)),
+                        escape_html(&elem.url)
+                    )
                 };
                 let mut children_string = String::new();
                 if !children.is_empty() {
                     children_string = format!("<ul>{children}</ul>");
                 }
-                let href = String::from("https://reddit.com") + &self.permalink;
-                let author = self.author.clone();
-                let ups = self.ups;
-                let span_data = self.data.strip_prefix(&self.url).unwrap();
+                let href = escape_html(&(String::from("https://reddit.com") + &elem.permalink));
+                let author = escape_html(elem.author());
+                let tags = elem
+                    .tags()
+                    .iter()
+                    .map(|o| format!(" <span class=\"tag\">{}</span>", escape_html(o)))
+                    .collect::<String>();
+                let ups = elem.ups;
+                let mut date = String::new();
+                if let Some(o) = &elem.created {
+                    date += &format!(
+                        " <time datetime=\"{}\">{}</time> ({})",
+                        date::iso(o),
+                        escape_html(&dates.absolute(o)),
+                        dates.relative(o)
+                    );
+                }
+                match &elem.edited {
+                    Edited::No => {}
+                    Edited::Unknown => date += " edited",
+                    Edited::At(o) => {
+                        date += &format!(
+                            " edited <time datetime=\"{}\" title=\"{}\">{}</time>",
+                            date::iso(o),
+                            escape_html(&dates.absolute(o)),
+                            dates.relative(o)
+                        );
+                    }
+                }
+                if !date.is_empty() {
+                    date = format!(" <span class=\"date\">{}</span>", date.trim_start());
+                }
+                let mut data = String::new();
+                if !elem.title.is_empty() {
+                    data += &format!("<h3>{}</h3>", escape_html(&elem.title));
+                }
+                data += &markdown::to_html(&elem.body);
+                if let Some(o) = &elem.media {
+                    data += &o.to_html(dates);
+                }
+                let mut class = String::from("element");
+                if elem.focused {
+                    class += " focused";
+                }
+                if elem.state.is_deleted() {
+                    class += " ";
+                    class += elem.state.as_str();
+                }
                 f.write_fmt(format_args!(
-                    "\n{indent}<div class=\"element\">
-                    {indent}<h4><a href=\"{href}\">{author}</a> ⬆️{ups}:</h4>
+                    "\n{indent}<div class=\"{class}\">
+                    {indent}<h4><a href=\"{href}\">{author}</a>{tags} ⬆️{ups}{date}:</h4>
                     {url}
-                    <span>{span_data}</span>
+                    <div class=\"data\">{data}</div>
                     {children_string}
                     \n{indent}</div>", //TODO: add human readable formatting
                 ))
@@ -167,45 +256,82 @@ impl std::fmt::Display for Element {
                     json_object.insert("author", JsonValue::String(elem.author.clone()));
                     json_object.insert("data", JsonValue::String(elem.data.clone()));
                     json_object.insert("ups", JsonValue::from(elem.ups));
-                    json_object.insert("edited", JsonValue::from(elem.edited));
-                    json_object.insert("depth", JsonValue::String(elem.depth.to_string()));
+                    json_object.insert(
+                        "created",
+                        JsonValue::from(elem.created.as_ref().map(date::iso)),
+                    );
+                    json_object.insert("edited", elem.edited.to_json());
+                    json_object.insert("depth", JsonValue::from(elem.depth));
                     json_object.insert("id", JsonValue::String(elem.id.clone()));
-                    json_object.insert("kind", JsonValue::String(elem.kind.clone()));
+                    json_object.insert("kind", JsonValue::from(elem.kind.as_str()));
                     json_object.insert("permalink", JsonValue::String(elem.permalink.clone()));
                     json_object.insert("url", JsonValue::String(elem.url.clone()));
                     json_object.insert("over_18", JsonValue::from(elem.over_18));
-                    for child in &elem.children {
-                        json_object.insert("children", json::from(parse_json_element(child)));
+                    let meta = &elem.meta;
+                    json_object.insert("score", JsonValue::from(meta.score));
+                    json_object.insert("upvote_ratio", JsonValue::from(meta.upvote_ratio));
+                    json_object.insert("link_flair", JsonValue::from(meta.link_flair()));
+                    json_object.insert("author_flair", JsonValue::from(meta.author_flair()));
+                    json_object.insert(
+                        "distinguished",
+                        JsonValue::from(meta.distinguished.as_deref()),
+                    );
+                    json_object.insert("stickied", JsonValue::from(meta.stickied));
+                    json_object.insert("locked", JsonValue::from(meta.locked));
+                    json_object.insert("is_submitter", JsonValue::from(meta.is_submitter));
+                    json_object.insert("controversiality", JsonValue::from(meta.controversiality));
+                    json_object.insert("awards", JsonValue::from(meta.awards));
+                    json_object.insert("subreddit", JsonValue::from(meta.subreddit.as_str()));
+                    json_object.insert("state", JsonValue::from(elem.state.as_str()));
+                    json_object.insert(
+                        "media",
+                        elem.media.as_ref().map_or(JsonValue::Null, Media::to_json),
+                    );
+                    if elem.focused {
+                        json_object.insert("focused", JsonValue::from(true));
                     }
+                    json_object.insert(
+                        "children",
+                        JsonValue::Array(
+                            elem.children
+                                .iter()
+                                .map(|o| parse_json_element(o).into())
+                                .collect(),
+                        ),
+                    );
                     json_object
                 }
-                let json_object = parse_json_element(self);
+                let json_object = parse_json_element(elem);
                 f.write_fmt(format_args!("{},\n", json_object.pretty(4)))
             }
-        };
+        }
     }
 }
 
 impl Element {
     //Only intended for testing.
     #[cfg(test)]
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         author: String,
         data: String,
-        kind: String,
+        kind: Kind,
         url: String, //url_overridden_by_dest
-        ups: usize,
+        ups: i64,
         children: Vec<Element>,
-        depth: String,
+        depth: usize,
         permalink: String,
         id: String,
         parent_id: String,
         over_18: bool,
-        created: usize,
-        edited: usize,
+        created: Option<DateTime<Utc>>,
+        edited: Edited,
     ) -> Self {
+        let state = State::classify(&author, &data, None);
         Self {
             author,
+            body: data.clone(),
+            title: String::new(),
             data,
             kind,
             url,
@@ -218,292 +344,322 @@ impl Element {
             over_18,
             created,
             edited,
+            focused: false,
+            state,
+            meta: Metadata::default(),
+            media: None,
         }
     }
 
-    pub fn create(child: &JsonValue, max_elements: usize) -> Option<Self> {
-        if get_safe!(ELEMENTS_COUNT) >= max_elements {
-            return None;
+    pub const fn formatted(&self, format: Format) -> FormattedElement<'_> {
+        FormattedElement {
+            element: self,
+            format,
+            dates: None,
         }
-        let data = &child["data"];
-        if *data == JsonValue::Null {
+    }
+
+    //ex. 2023-01-30 12:34, 3h ago, edited 1h ago
+    pub fn date_label(&self, dates: &DateFormat) -> Option<String> {
+        let label = self
+            .created
+            .map(|o| format!("{}, {}", dates.absolute(&o), dates.relative(&o)));
+        let edited = match &self.edited {
+            Edited::No => return label,
+            Edited::Unknown => String::from("edited"),
+            Edited::At(o) => format!("edited {}", dates.relative(o)),
+        };
+        Some(match label {
+            Some(o) => format!("{o}, {edited}"),
+            None => edited,
+        })
+    }
+
+    //The text of the element (url, title, selftext and body)
+    pub fn data(&self) -> &str {
+        &self.data
+    }
+
+    pub fn is_post(&self) -> bool {
+        self.kind == Kind::Post
+    }
+
+    pub const fn kind(&self) -> Kind {
+        self.kind
+    }
+
+    //True if the comment was deleted by it's author or removed by a moderator
+    pub fn is_deleted(&self) -> bool {
+        self.kind == Kind::Comment && self.state.is_deleted()
+    }
+
+    //Reddit sometimes leaves out the author of removed comments
+    pub fn author(&self) -> &str {
+        if self.author.is_empty() {
+            "[deleted]"
+        } else {
+            &self.author
+        }
+    }
+
+    //Short labels of the metadata worth showing next to the author, ex. OP, moderator, stickied
+    pub fn tags(&self) -> Vec<String> {
+        let meta = &self.meta;
+        let mut tags = Vec::new();
+        if let Some(o) = self.state.label() {
+            tags.push(o.to_owned());
+        }
+        if self.focused {
+            tags.push(String::from("linked comment"));
+        }
+        if self.is_post() {
+            if !meta.subreddit.is_empty() {
+                tags.push(format!("r/{}", meta.subreddit));
+            }
+            if let Some(o) = meta.upvote_ratio {
+                tags.push(format!("{:.0}% upvoted", o * 100.0));
+            }
+            if let Some(o) = meta.link_flair() {
+                tags.push(format!("flair: {o}"));
+            }
+        }
+        if meta.is_submitter {
+            tags.push(String::from("OP"));
+        }
+        if let Some(o) = &meta.distinguished {
+            tags.push(o.clone());
+        }
+        if meta.stickied {
+            tags.push(String::from("stickied"));
+        }
+        if meta.locked {
+            tags.push(String::from("locked"));
+        }
+        if meta.is_controversial() {
+            tags.push(String::from("controversial"));
+        }
+        if meta.awards > 0 {
+            tags.push(format!(
+                "{} award{}",
+                meta.awards,
+                if meta.awards == 1 { "" } else { "s" }
+            ));
+        }
+        if let Some(o) = meta.author_flair() {
+            tags.push(format!("author flair: {o}"));
+        }
+        tags
+    }
+
+    //Single things built with the json crate (ex. the profile's), responses are parsed with init
+    pub fn create(child: &JsonValue, session: &FetchSession) -> Option<Self> {
+        let child = serde_json::from_str(&child.dump()).ok()?;
+        Self::from_value(child, session)
+    }
+
+    //Parse a listing child and it's replies
+    fn from_value(mut child: serde_json::Value, session: &FetchSession) -> Option<Self> {
+        if session.reached_max() {
             return None;
         }
+        let data = child.get_mut("data").filter(|o| !o.is_null())?;
+        //The replies are parsed to elements of their own
+        let replies = data.as_object_mut().and_then(|o| o.remove("replies"));
+        let data = &child["data"];
+
         //If the element lists more elements(it's kind is more)
-        if child["kind"].clone() == "more" {
-            unsafe { MORE_ELEMENTS_COUNT += data["count"].as_usize().map_or(0, |o| o) }
-            for more_element in data["children"].members() {
-                unsafe {
-                    MORE_ELEMENTS.push(more_element.to_string());
+        if child["kind"] == "more" {
+            let count = data["count"]
+                .as_u64()
+                .and_then(|o| usize::try_from(o).ok())
+                .unwrap_or(0);
+            //'continue this thread' links, they don't list any ids
+            if count == 0 {
+                let parent_id = data["parent_id"].as_str().unwrap_or_default();
+                session.push_continue_stub(parent_id.get(3..).unwrap_or(parent_id).to_owned());
+            }
+            session.add_more_elements_count(count);
+            for more_element in data["children"].as_array().into_iter().flatten() {
+                if let Some(o) = more_element.as_str() {
+                    session.push_more_element(o.to_owned());
                 }
             }
 
             return None;
         }
 
+        let Thing { kind, data: typed } = Thing::deserialize(&child).unwrap_or_else(|e| {
+            //Keep what can be read, losing the element would lose it's replies too
+            let id = data["id"].as_str().unwrap_or_default().to_owned();
+            session.push_malformed(id, e.to_string());
+            Thing::lenient(&child)
+        });
+        let media = if kind == Kind::Post {
+            Media::from_data(&typed)
+        } else {
+            None
+        };
+
         let mut total_data = String::new();
 
-        let mut add_to_total = |var: String| {
+        let mut add_to_total = |var: &str| {
             if !var.is_empty() {
                 if !total_data.is_empty() {
                     total_data += "\n";
                 }
-                total_data += &var;
+                total_data += var;
             }
         };
 
-        let n_comments = get_data_wrapper!(data, num_comments, String::new());
-        if !n_comments.is_empty() {
-            unsafe { NUM_COMMENTS = n_comments.parse::<usize>().map_or(0, |o| o) }
+        if let Some(o) = typed.num_comments {
+            session.set_num_comments(o);
         }
 
-        let title_ = get_data_wrapper!(data, title, String::new());
-        let url = get_data_wrapper!(data, url, String::new());
-        let selftext = get_data_wrapper!(data, selftext, String::new());
-        let body = get_data_wrapper!(data, body, String::new());
-        add_to_total(url);
-        add_to_total(title_);
-        add_to_total(selftext);
-        add_to_total(body);
+        add_to_total(&typed.url);
+        add_to_total(&typed.title);
+        add_to_total(&typed.selftext);
+        add_to_total(&typed.body);
 
-        let author = get_data_wrapper!(data, author, String::new());
+        let body = if kind == Kind::Post {
+            typed.selftext
+        } else {
+            typed.body
+        };
+        let state = State::classify(&typed.author, &body, typed.removed_by_category.as_deref());
 
-        unsafe {
-            ELEMENTS_COUNT += 1;
+        if typed.name.starts_with("t3_") {
+            session.set_link_id(typed.name.clone());
         }
 
+        session.inc_elements_count();
+
         Some(Self {
-            author,
+            author: typed.author,
             //The data only stores some of the acctual text data
             data: total_data,
-            children: Self::get_replies(data, max_elements).map_or_else(|_| Vec::new(), |o| o),
-            ups: get_data_wrapper!(data, ups, "0".to_string())
-                .parse::<usize>()
-                .map_or(0usize, |o| o),
-            url: get_data_wrapper!(data, url_overridden_by_dest, String::new()),
-            //a hacky way, but "kind" attribute is higher in the json tree so it would be a pain in the butt to get it that way
-            kind: get_data_wrapper!(data, name, String::new())[0..2].to_owned(),
-            depth: get_data_wrapper!(data, depth, "0".to_string()),
-            permalink: get_data_wrapper!(data, permalink, String::new()),
-            id: get_data_wrapper!(data, id, String::new()),
-            parent_id: {
-                //parent_id also stores the kind as in XY_<id>, where XY is the kind
-                let mut pid = get_data_wrapper!(data, parent_id, String::new());
-                if pid.len() > 3 {
-                    pid = pid[3..].to_string();
-                }
-                pid
-            },
-            over_18: get_data_wrapper!(data, over_18, String::from("false")) == *"true",
-            created: get_data_wrapper!(data, created, usize::MAX.to_string())
-                .parse::<f32>()
-                .map_or(usize::MAX, |o| o as usize),
-            //TODO: change edited to an enum (false/timestamp as a uszie)
-            edited: get_data_wrapper!(data, edited, usize::MAX.to_string())
-                .parse::<f32>()
-                .map_or(usize::MAX, |o| o as usize),
+            title: typed.title,
+            body,
+            children: Self::get_replies(replies, session).unwrap_or_default(),
+            ups: typed.ups,
+            url: typed.url_overridden_by_dest,
+            kind,
+            depth: typed.depth,
+            permalink: typed.permalink,
+            id: typed.id,
+            //parent_id also stores the kind as in XY_<id>, where XY is the kind
+            parent_id: typed
+                .parent_id
+                .get(3..)
+                .map_or(typed.parent_id.clone(), str::to_owned),
+            over_18: typed.over_18,
+            created: typed
+                .created_utc
+                .or(typed.created)
+                .and_then(date::from_timestamp),
+            edited: typed.edited,
+            focused: false,
+            state,
+            meta: typed.meta,
+            media,
         })
     }
 
-    pub fn init(data: &JsonValue, max_elements: usize) -> Vec<Self> {
+    //Parse the listings of a post's response, the children are taken out of data
+    pub fn init(mut data: serde_json::Value, session: &FetchSession) -> Vec<Self> {
         let mut elements = Vec::<Self>::new();
 
-        for member in data.members() {
-            for child in member["data"]["children"].members() {
-                if get_safe!(ELEMENTS_COUNT) >= max_elements {
-                    break;
-                }
-                //If created element isn't empty (Ok) push it.
-                if let Some(o) = Self::create(child, max_elements) {
-                    elements.push(o);
-                }
-            }
+        for member in data.as_array_mut().into_iter().flatten() {
+            elements.extend(Self::take_children(member, "/data/children", session));
         }
         elements
     }
 
-    fn get_replies(element: &JsonValue, max_elements: usize) -> Result<Vec<Self>, Empty> {
-        let mut out = Vec::<Self>::new();
-        if element["replies"] != JsonValue::Null {
-            for child in element["replies"]["data"]["children"].members() {
-                if get_safe!(ELEMENTS_COUNT) >= max_elements {
-                    break;
-                }
-                let Some(element) = Self::create(child, max_elements) else {
-                    continue;
-                };
+    fn take_children(
+        listing: &mut serde_json::Value,
+        pointer: &str,
+        session: &FetchSession,
+    ) -> Vec<Self> {
+        match listing.pointer_mut(pointer).map(serde_json::Value::take) {
+            Some(serde_json::Value::Array(o)) => Self::from_values(o, session),
+            _ => Vec::new(),
+        }
+    }
 
-                out.push(element);
+    fn from_values(children: Vec<serde_json::Value>, session: &FetchSession) -> Vec<Self> {
+        let mut out = Vec::<Self>::new();
+        for child in children {
+            if session.reached_max() {
+                break;
+            }
+            //If created element isn't empty (Ok) push it.
+            if let Some(o) = Self::from_value(child, session) {
+                out.push(o);
             }
         }
+        out
+    }
+
+    fn get_replies(
+        replies: Option<serde_json::Value>,
+        session: &FetchSession,
+    ) -> Result<Vec<Self>, Empty> {
+        let out = replies.map_or_else(Vec::new, |mut o| {
+            Self::take_children(&mut o, "/data/children", session)
+        });
         if !out.is_empty() {
             return Ok(out);
         }
         Err(Empty {})
     }
 
-    fn get_data(element: &JsonValue, field: &str) -> Result<String, Empty> {
-        if element[field] != JsonValue::Null {
-            return Ok(element[field].to_string());
-        }
-        Err(Empty {})
+    //Request up to MORE_CHILDREN_LIMIT comments listed by 'more' elements with one morechildren call
+    //Returns the parsed elements (flat, in the order reddit sent them) and the raw response
+    pub async fn get_more_children(
+        origin: &str,
+        link_id: &str,
+        ids: &[String],
+        session: &FetchSession,
+    ) -> Result<(Vec<Self>, String), SrpError> {
+        let url = format!(
+            "{origin}/api/morechildren.json?api_type=json&raw_json=1&limit_children=false&link_id={link_id}&children={}",
+            ids.join(",")
+        );
+        let data = session.scheduler.request(&url).await?.text().await?;
+        let json_data = serde_json::from_str(&data)?;
+
+        Ok((Self::parse_more_children(json_data, session)?, data))
     }
 
-    //Recursively check every element, and if the first element matches append to it
-    fn append_element(x: &mut Vec<Self>, y: &mut Vec<Self>) {
-        for element in &mut *y {
-            if x.is_empty() {
-                break;
-            }
-            if *element == x[0] {
-                x.remove(0);
-                unsafe {
-                    ELEMENTS_COUNT -= 1;
-                }
-                element.children.append(x);
-                break;
-            }
-        }
-        for e in y {
-            Self::append_element(x, &mut e.children);
+    //Parse a morechildren response, the elements are flat, put them together with a TreeBuilder
+    pub fn parse_more_children(
+        mut json_data: serde_json::Value,
+        session: &FetchSession,
+    ) -> Result<Vec<Self>, SrpError> {
+        let errors = &json_data["json"]["errors"];
+        if errors.as_array().is_some_and(|o| !o.is_empty()) {
+            return Err(SrpError::JsonParse(format!(
+                "morechildren errors: {errors}"
+            )));
         }
+
+        Ok(Self::take_children(
+            &mut json_data,
+            "/json/data/things",
+            session,
+        ))
     }
 
-    #[async_recursion::async_recursion]
+    //Request a single 'more' element using the post's permalink
+    //Returns the parsed elements (the post and the element's subtree) and the raw response
     pub async fn get_more_element(
-        verbosity: &Verbosity,
-        print_timestamps: bool,
-        base_url: String,
-        idx: Arc<Mutex<f64>>,
-        more_start: SystemTime,
-        last_line_length: Arc<Mutex<usize>>,
-        elements: Arc<Mutex<Vec<Self>>>,
-        max_comments: usize,
-        mut retries: usize,
-    ) -> Option<String> {
-        if get_safe!(ELEMENTS_COUNT) >= max_comments {
-            return None;
-        }
-        let Ok(res) = request(base_url.clone(), None).await else {
-            if retries < 3 {
-                retries += 1;
-                return Self::get_more_element(
-                    verbosity,
-                    print_timestamps,
-                    base_url,
-                    idx,
-                    more_start,
-                    last_line_length,
-                    elements,
-                    max_comments,
-                    retries,
-                )
-                .await;
-            }
-            return None;
-        };
-
-        let Ok(data) = res.text().await else {
-            if retries < 3 {
-                retries += 1;
-                return Self::get_more_element(
-                    verbosity,
-                    print_timestamps,
-                    base_url,
-                    idx,
-                    more_start,
-                    last_line_length,
-                    elements,
-                    max_comments,
-                    retries,
-                )
-                .await;
-            }
-            return None;
-        };
+        url: &str,
+        session: &FetchSession,
+    ) -> Result<(Vec<Self>, String), SrpError> {
+        let data = session.scheduler.request(url).await?.text().await?;
 
-        let json_data = match json::parse(&data) {
-            Ok(o) => o,
-            Err(e) => {
-                println!("Failed to parse json data with error: {e}");
-                std::process::exit(0);
-            }
-        };
+        let json_data = serde_json::from_str(&data)?;
 
         //Parse json data to elements
-        let mut e = Self::init(&json_data, max_comments);
-
-        //Code block, bcs rust's mutex is unlocked at the end of a code block (improves performence)
-        {
-            let idx_ = *idx.lock().unwrap();
-            let last_line_length_ = *last_line_length.lock().unwrap();
-
-            //calculate % of progress as a 64bit float
-            let precent = idx_ / (get_safe!(MORE_ELEMENTS).len() as f64) * 100f64;
-
-            //get time passed since start of getting 'more' elements
-            let passed = std::time::SystemTime::now()
-                .duration_since(more_start)
-                .unwrap()
-                .as_secs_f64();
-
-            //Get estimated time
-            let eta = get_safe!(MORE_ELEMENTS).len() as f64 / (idx_ / passed);
-
-            //Format the line to be printed
-            let mut line = format!(
-                "[{}::{}] {}{idx_} / {} {:.2}% runtime: {} ETA: {}",
-                style("CLI").bold(),
-                style("INFO").bold().green(),
-                get_timestamp(print_timestamps),
-                get_safe!(MORE_ELEMENTS).len(),
-                precent,
-                convert_time(passed),
-                convert_time(eta - passed)
-            );
-
-            let line_length = line.len();
-
-            //Make sure there is no residual chars from last line
-            if line_length < last_line_length_ {
-                line += &" ".repeat(last_line_length_ - line_length);
-            }
-            let mut last_line_length_ = last_line_length.lock().unwrap();
-            *last_line_length_ = line_length;
-
-            //Print the line and flush stdout
-            //If you don't flush stdout not every line will be printed,
-            //Because print! doesn't flush as oppose to println!
-            if *verbosity == Verbosity::High || *verbosity == Verbosity::Moderate {
-                print!("\r{line}");
-                std::io::stdout().flush().unwrap();
-            }
-            let mut idx_ = idx.lock().unwrap();
-            *idx_ += 1.0;
-        }
-
-        //
-        {
-            let mut elements_ = match elements.lock() {
-                Ok(o) => o,
-                Err(e) => {
-                    println!("{:?} panic:\n{}", thread::current(), e);
-                    return None;
-                }
-            };
-            //This is intended
-            #[allow(clippy::collapsible_if)]
-            if e.len() < 2 {
-                if !e.is_empty() {
-                    if !e[0].children.is_empty() {
-                        elements_.append(&mut e[0].children);
-                    }
-                }
-                return None;
-            }
-
-            Self::append_element(&mut e, &mut elements_);
-        }
-        Some(data)
+        Ok((Self::init(json_data, session), data))
     }
 }
@@ -0,0 +1,75 @@
+use std::fmt::Display;
+
+//Every way an archive run can fail.
+//Each variant maps to its own exit code, see SrpError::exit_code
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SrpError {
+    //Invalid arguments or url
+    Validation(String),
+    //The request couldn't be sent or the response couldn't be read
+    Network(String),
+    //Reddit responded with a non success status code (status, url)
+    HttpStatus(u16, String),
+    //The response isn't valid json, or isn't shaped like a reddit listing
+    JsonParse(String),
+    //Reading or writing files failed
+    Filesystem(String),
+}
+
+impl SrpError {
+    //Exit codes:
+    // 0 - success
+    // 1 - other/unknown error
+    // 2 - validation (invalid usage)
+    // 3 - network
+    // 4 - http status
+    // 5 - json parse
+    // 6 - filesystem
+    pub const fn exit_code(&self) -> i32 {
+        match self {
+            Self::Validation(_) => 2,
+            Self::Network(_) => 3,
+            Self::HttpStatus(..) => 4,
+            Self::JsonParse(_) => 5,
+            Self::Filesystem(_) => 6,
+        }
+    }
+}
+
+impl Display for SrpError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Validation(e) => write!(f, "{e}"),
+            Self::Network(e) => write!(f, "Network error: {e}"),
+            Self::HttpStatus(status, url) => write!(f, "HTTP status {status} from {url}"),
+            Self::JsonParse(e) => write!(f, "Parsing to JSON error: {e}"),
+            Self::Filesystem(e) => write!(f, "Filesystem error: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for SrpError {}
+
+impl From<std::io::Error> for SrpError {
+    fn from(e: std::io::Error) -> Self {
+        Self::Filesystem(e.to_string())
+    }
+}
+
+impl From<json::Error> for SrpError {
+    fn from(e: json::Error) -> Self {
+        Self::JsonParse(e.to_string())
+    }
+}
+
+impl From<reqwest::Error> for SrpError {
+    fn from(e: reqwest::Error) -> Self {
+        Self::Network(e.to_string())
+    }
+}
+
+impl From<serde_json::Error> for SrpError {
+    fn from(e: serde_json::Error) -> Self {
+        Self::JsonParse(e.to_string())
+    }
+}
@@ -0,0 +1,317 @@
+extern crate tokio;
+
+pub mod batch;
+pub mod checkpoint;
+pub mod cli;
+pub mod client;
+pub mod date;
+pub mod diff;
+pub mod element;
+pub mod error;
+pub mod listing;
+pub mod markdown;
+pub mod media;
+pub mod model;
+pub mod more;
+pub mod offline;
+mod output_writer;
+pub mod profile;
+pub mod reconcile;
+pub mod scheduler;
+pub mod session;
+pub mod snapshot;
+pub mod tree;
+pub mod url;
+pub mod utils;
+pub mod watch;
+
+mod tests;
+
+use std::{sync::Arc, time::SystemTime};
+
+use checkpoint::Checkpoint;
+use cli::CLI;
+use date::DateFormat;
+use element::{Element, Format};
+use error::SrpError;
+pub use more::fetch_more;
+use scheduler::RequestScheduler;
+use session::FetchSession;
+use utils::convert_time;
+
+//Request the post from cli.url and parse it to json
+pub async fn fetch(cli: &CLI, session: &FetchSession) -> Result<serde_json::Value, SrpError> {
+    let start = SystemTime::now();
+
+    cli.print_infom(format!("Requesting content from {}:", cli.url));
+    let res = session.scheduler.request(&cli.url).await?;
+
+    let data = res
+        .text()
+        .await
+        .map_err(|e| SrpError::Network(format!("Failed to read the response: {e}")))?;
+    cli.print_infom(format!(
+        "Success in {}",
+        convert_time(start.elapsed().unwrap_or_default().as_secs_f64())
+    ));
+
+    let json_data = serde_json::from_str(&data)?;
+    cli.print_info("Parsing to JSON: success");
+
+    if cli.save_tmp_files {
+        let tmp_dir = std::path::Path::new(utils::TMP_DIR);
+        if !tmp_dir.exists() {
+            std::fs::create_dir(tmp_dir)?;
+        }
+
+        std::fs::write(
+            utils::TMP_DIR.to_owned() + "raw.json",
+            serde_json::to_string_pretty(&json_data)?,
+        )?;
+        cli.print_info("Writing to JSON file: success");
+    }
+
+    Ok(json_data)
+}
+
+//Parse the json data of a post to the element tree
+pub fn parse(
+    json_data: serde_json::Value,
+    session: &FetchSession,
+) -> Result<Vec<Element>, SrpError> {
+    let elements = Element::init(json_data, session);
+    if elements.is_empty() {
+        return Err(SrpError::JsonParse(String::from(
+            "Parsing to elements: fail.",
+        )));
+    }
+    Ok(elements)
+}
+
+//Render the elements to a string in the given format
+//source is used as the title/source of the document
+pub fn render(elements: &[Element], format: Format, dates: &DateFormat, source: &str) -> String {
+    let mut content = String::new();
+
+    //Write begining of the file:
+    match format {
+        Format::Default => {
+            content += &format!(
+                "# {{indent}} {{ups}} {{author}} ({{date}}): {{contnet}}\n\nSource: {source}"
+            );
+        }
+        Format::HTML => {
+            content +=
+                &include_str!("html_file.html").replace("{title}", &utils::escape_html(source));
+        }
+        Format::JSON => content += "{\"data\":[",
+    }
+
+    //Write every element to the output.
+    //For formatting see element.rs:
+    //                   impl std::fmt::Display for FormattedElement
+    for elem in elements {
+        content += &format!("{}", elem.formatted(format).with_dates(dates));
+    }
+
+    //Write the end:
+    match format {
+        Format::Default => {}
+        Format::HTML => content += "\t</div>\n</body>\n</html>",
+        Format::JSON => {
+            if let Some(r) = content.strip_suffix(",\n") {
+                content = r.to_owned();
+            }
+            content += "\n]}";
+        }
+    }
+    content
+}
+
+//Run the whole pipeline for cli.url and write the result to the output specified by the cli
+pub async fn archive(cli: &CLI) -> Result<(), SrpError> {
+    let start = SystemTime::now();
+
+    if let Some(profile) = &cli.profile {
+        return profile::archive(cli, profile).await;
+    }
+
+    if cli.offline {
+        let session = FetchSession::new(cli.format, cli.max_comments);
+        let (elements, source) = offline::load(cli, &session, &cli.url)?;
+        let cli = CLI {
+            base_url: source,
+            ..cli.clone()
+        };
+        return finish(&cli, &session, elements, start);
+    }
+
+    let scheduler = Arc::new(RequestScheduler::new(cli.client.build(&cli.url)?));
+    archive_with(cli, scheduler).await
+}
+
+//Same as archive, but every request goes through scheduler, which can be shared by many posts
+pub async fn archive_with(cli: &CLI, scheduler: Arc<RequestScheduler>) -> Result<(), SrpError> {
+    let start = SystemTime::now();
+
+    let resolved_cli = resolve_url(cli, &scheduler).await?;
+    let cli = resolved_cli.as_ref().unwrap_or(cli);
+
+    //Every post has it's own checkpoint, so batches and concurrent runs don't overwrite each other
+    let checkpoint_dir = Checkpoint::dir_for(&cli.url, cli.host.as_deref());
+    let resumed = if cli.resume {
+        let checkpoint = Checkpoint::open(&checkpoint_dir, &cli.url)?;
+        if checkpoint.is_none() {
+            cli.print_warning("No checkpoint found for this url, starting over");
+        }
+        checkpoint
+    } else {
+        None
+    };
+    let is_resumed = resumed.is_some();
+    let checkpoint = match resumed {
+        Some(o) => o,
+        None => Checkpoint::create(&checkpoint_dir, &cli.url)?,
+    };
+
+    let session = Arc::new(
+        FetchSession::new(cli.format, cli.max_comments)
+            .with_scheduler(scheduler)
+            .with_checkpoint(checkpoint),
+    );
+
+    let elements = if is_resumed {
+        let elements = session
+            .checkpoint
+            .as_ref()
+            .map_or_else(|| Ok(Vec::new()), |o| o.load(cli, &session))?;
+        cli.print_infom("Resuming from the checkpoint: success.");
+        elements
+    } else {
+        let json_data = match fetch(cli, &session).await {
+            Ok(o) => o,
+            Err(e) => {
+                //Nothing to resume yet
+                remove_checkpoint(&session)?;
+                return Err(e);
+            }
+        };
+        if let Some(checkpoint) = &session.checkpoint {
+            checkpoint.save_raw(&json_data)?;
+        }
+        let elements = parse(json_data, &session)?;
+        cli.print_info("Parsing to elements: success.");
+        elements
+    };
+
+    let result = match fetch_more(cli, &session, elements).await {
+        Ok(elements) => finish(cli, &session, elements, start),
+        Err(e) => Err(e),
+    };
+
+    //Keep the checkpoint if something failed, so the failed requests can be retried with --resume
+    if result.is_ok() && session.failed().is_empty() {
+        remove_checkpoint(&session)?;
+    } else if result.is_ok() {
+        cli.print_infom("Some requests failed, run again with --resume to retry them");
+    } else {
+        cli.print_infom("Run again with --resume to continue from the checkpoint");
+    }
+    result
+}
+
+//Share links only redirect to the post, so follow them first
+//--context is applied here too, so it works for every post of a batch
+//Returns the cli with the new url, None if it didn't change
+pub(crate) async fn resolve_url(
+    cli: &CLI,
+    scheduler: &RequestScheduler,
+) -> Result<Option<CLI>, SrpError> {
+    match url::RedditUrl::parse(&cli.url, cli.host.as_deref()) {
+        Ok(o) if o.is_share() || cli.context.is_some() => {
+            if o.is_share() {
+                cli.print_infom(format!("Resolving the share link {}", cli.url));
+            }
+            let mut resolved = o.resolve(scheduler, cli.host.as_deref()).await?;
+            if let Some(n) = cli.context {
+                resolved.set_context(n);
+            }
+            let (url, base_url) = resolved.urls();
+            Ok(Some(CLI {
+                url,
+                base_url,
+                ..cli.clone()
+            }))
+        }
+        _ => Ok(None),
+    }
+}
+
+fn remove_checkpoint(session: &FetchSession) -> Result<(), SrpError> {
+    if let Some(checkpoint) = &session.checkpoint {
+        checkpoint.remove()?;
+    }
+    Ok(())
+}
+
+//Elements that reddit sent in an unexpected shape, they're kept with the fields that could be read
+pub(crate) fn warn_malformed(cli: &CLI, session: &FetchSession) {
+    for (id, e) in session.malformed() {
+        cli.print_warning(format!("Element {id} was only partly parsed: {e}"));
+    }
+}
+
+//Sort, filter and write the elements to the output
+pub(crate) fn finish(
+    cli: &CLI,
+    session: &FetchSession,
+    mut elements: Vec<Element>,
+    start: SystemTime,
+) -> Result<(), SrpError> {
+    if session.elements_count() == 0 {
+        return Err(SrpError::JsonParse(String::from(
+            "Error, returned 0 elements!",
+        )));
+    }
+
+    warn_malformed(cli, session);
+
+    //Comment permalinks mark the comment they point to
+    let focus = url::RedditUrl::parse(&cli.url, cli.host.as_deref())
+        .ok()
+        .and_then(|o| o.comment().map(str::to_owned));
+    if let Some(id) = &focus {
+        if !tree::focus(&mut elements, id) {
+            cli.print_warning(format!("The linked comment {id} wasn't returned"));
+        }
+    }
+
+    //Before filtering, so filtered out elements aren't reported as missing
+    let report = reconcile::Report::new(cli, session, &elements);
+
+    //Sort elements (except the first one which is the parent element or the reddit post)
+    elements = utils::sort_elements_(elements, cli)?;
+
+    utils::write_to_output(cli, session, &elements, start)?;
+
+    //Reddit only returns the comment's thread, so the count of the whole post doesn't apply
+    match &focus {
+        Some(id) => cli.print_infom(format!(
+            "Archived the thread of comment {id}: {} comments",
+            report.fetched
+        )),
+        None => report.print(cli),
+    }
+    if let Some(path) = &cli.report_path {
+        std::fs::write(path, report.to_json().pretty(2)).map_err(|e| {
+            SrpError::Filesystem(format!("Failed to write the report to {path}: {e}"))
+        })?;
+    }
+
+    if cli.delete_tmp {
+        if let Err(e) = utils::delete_tmp() {
+            cli.print_warning(e);
+        }
+    }
+    Ok(())
+}
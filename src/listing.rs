@@ -0,0 +1,200 @@
+use std::{collections::HashSet, sync::Arc};
+
+use json::JsonValue;
+
+use crate::{
+    batch::{self, BatchResult},
+    cli::CLI,
+    error::SrpError,
+    scheduler::RequestScheduler,
+    utils,
+};
+
+//Max number of posts reddit returns in one page of a listing
+pub const PAGE_LIMIT: usize = 100;
+//Valid values of the t= parameter
+pub const TIME_RANGES: [&str; 6] = ["hour", "day", "week", "month", "year", "all"];
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ListingSort {
+    #[default]
+    Hot,
+    New,
+    Top,
+    Rising,
+    Controversial,
+}
+
+impl ListingSort {
+    pub fn parse(sort: &str) -> Option<Self> {
+        match sort.trim().to_lowercase().as_str() {
+            "hot" => Some(Self::Hot),
+            "new" => Some(Self::New),
+            "top" => Some(Self::Top),
+            "rising" => Some(Self::Rising),
+            "controversial" => Some(Self::Controversial),
+            _ => None,
+        }
+    }
+
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Hot => "hot",
+            Self::New => "new",
+            Self::Top => "top",
+            Self::Rising => "rising",
+            Self::Controversial => "controversial",
+        }
+    }
+
+    //Only top and controversial can be limited to a time range
+    pub const fn has_time_range(self) -> bool {
+        matches!(self, Self::Top | Self::Controversial)
+    }
+}
+
+//A subreddit listing, ex. reddit.com/r/rust/top/?t=week
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Listing {
+    pub subreddit: String,
+    pub sort: ListingSort,
+    //One of TIME_RANGES
+    pub time: Option<String>,
+    //Number of posts to archive
+    pub limit: usize,
+}
+
+impl Listing {
+    //Parse a subreddit url, None if it isn't one (ex. it's a post)
+    pub fn from_url(url: &str) -> Option<Self> {
+        let url = url.trim();
+        let (path, query) = url.split_once('?').unwrap_or((url, ""));
+        let path = path.trim_end_matches('/').trim_end_matches(".json");
+        let mut segments = path
+            .split('/')
+            .skip_while(|o| *o != "r")
+            .skip(1)
+            .filter(|o| !o.is_empty());
+        let subreddit = segments.next()?;
+        let sort = match segments.next() {
+            Some(o) => ListingSort::parse(o)?,
+            None => ListingSort::default(),
+        };
+        if segments.next().is_some() {
+            return None;
+        }
+        let time = query
+            .split('&')
+            .find_map(|o| o.strip_prefix("t="))
+            .filter(|o| TIME_RANGES.contains(o))
+            .map(str::to_owned);
+        Some(Self {
+            subreddit: subreddit.to_owned(),
+            sort,
+            time,
+            limit: 25,
+        })
+    }
+
+    //Url of the subreddit, ex. https://www.reddit.com/r/rust/
+    pub fn base_url(&self, origin: &str) -> String {
+        format!("{origin}/r/{}/", self.subreddit)
+    }
+
+    //Url of the page of the listing after the post `after` (it's fullname)
+    pub fn page_url(&self, origin: &str, after: Option<&str>, limit: usize) -> String {
+        let mut url = format!(
+            "{origin}/r/{}/{}.json?raw_json=1&limit={limit}",
+            self.subreddit,
+            self.sort.as_str()
+        );
+        if let Some(time) = self.time.as_ref().filter(|_| self.sort.has_time_range()) {
+            url += &format!("&t={time}");
+        }
+        if let Some(after) = after {
+            url += &format!("&after={after}");
+        }
+        url
+    }
+
+    //Page through the listing, returns the urls of up to self.limit posts
+    pub async fn fetch_posts(
+        &self,
+        cli: &CLI,
+        scheduler: &RequestScheduler,
+        origin: &str,
+    ) -> Result<Vec<String>, SrpError> {
+        let mut seen = HashSet::new();
+        let children = fetch_pages(cli, scheduler, self.limit, |after, limit| {
+            self.page_url(origin, after, limit)
+        })
+        .await?;
+        Ok(children
+            .iter()
+            .filter_map(|o| o["data"]["permalink"].as_str())
+            .filter(|o| seen.insert(o.to_owned()))
+            .map(|o| origin.to_owned() + o)
+            .collect())
+    }
+}
+
+//Page through a listing using it's after tokens, returns up to limit children
+//page_url gets the fullname to continue after and the number of children to get
+pub async fn fetch_pages(
+    cli: &CLI,
+    scheduler: &RequestScheduler,
+    limit: usize,
+    page_url: impl Fn(Option<&str>, usize) -> String,
+) -> Result<Vec<JsonValue>, SrpError> {
+    let mut out = Vec::new();
+    let mut after: Option<String> = None;
+    while out.len() < limit {
+        let url = page_url(after.as_deref(), (limit - out.len()).min(PAGE_LIMIT));
+        cli.print_info(format!("Requesting {url}"));
+        let data = scheduler.request(&url).await?.text().await?;
+        let mut json_data = json::parse(&data)?;
+
+        let children = json_data["data"]["children"].take();
+        if children.is_empty() {
+            break;
+        }
+        for child in children.members() {
+            if out.len() < limit {
+                out.push(child.clone());
+            }
+        }
+
+        match json_data["data"]["after"].as_str() {
+            Some(o) => after = Some(o.to_owned()),
+            None => break,
+        }
+    }
+    Ok(out)
+}
+
+//Archive every post of the listing, like in batch mode
+pub async fn run(cli: &CLI, listing: &Listing) -> Result<Vec<BatchResult>, SrpError> {
+    let scheduler = Arc::new(RequestScheduler::new(cli.client.build(&cli.url)?));
+    let origin = utils::url_origin(&cli.base_url);
+
+    cli.print_infom(format!(
+        "Getting {} {} posts of r/{}{}",
+        listing.limit,
+        listing.sort.as_str(),
+        listing.subreddit,
+        match &listing.time {
+            Some(o) if listing.sort.has_time_range() => format!(" (t={o})"),
+            _ => String::new(),
+        }
+    ));
+    let posts = listing.fetch_posts(cli, &scheduler, &origin).await?;
+    if posts.is_empty() {
+        return Err(SrpError::JsonParse(format!(
+            "No posts in r/{}",
+            listing.subreddit
+        )));
+    }
+    cli.print_infom(format!("Got {} posts", posts.len()));
+
+    Ok(batch::archive_urls(cli, &scheduler, posts).await)
+}
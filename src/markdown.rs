@@ -0,0 +1,181 @@
+use pulldown_cmark::{html, Event, Options, Parser, Tag, TagEnd};
+
+use crate::{url::REDDIT_ORIGIN, utils::escape_html};
+
+//>! and !< of spoilers are swapped for these before parsing, or >! would start a quote
+const SPOILER_START: char = '\u{E000}';
+const SPOILER_END: char = '\u{E001}';
+
+//Render reddit flavoured markdown to html
+//Raw html in the text is escaped, links are limited to http(s) and mailto,
+//spoilers (>!text!<) and superscript (^word, ^(some words)) are supported
+pub fn to_html(text: &str) -> String {
+    let text = mark_spoilers(text);
+    let options = Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH;
+    let mut inline = Inline::default();
+    let mut in_code_block = false;
+    let events = Parser::new_ext(&text, options).flat_map(|event| match event {
+        Event::Start(Tag::CodeBlock(o)) => {
+            in_code_block = true;
+            vec![Event::Start(Tag::CodeBlock(o))]
+        }
+        Event::End(TagEnd::CodeBlock) => {
+            in_code_block = false;
+            vec![Event::End(TagEnd::CodeBlock)]
+        }
+        //Reddit doesn't have html blocks, their text is shown as a paragraph
+        Event::Start(Tag::HtmlBlock) => vec![Event::Start(Tag::Paragraph)],
+        Event::End(TagEnd::HtmlBlock) => vec![
+            Event::InlineHtml(inline.close_all().into()),
+            Event::End(TagEnd::Paragraph),
+        ],
+        Event::Text(o) | Event::Html(o) | Event::InlineHtml(o) if !in_code_block => {
+            vec![Event::InlineHtml(inline.render(&o).into())]
+        }
+        Event::Text(o) | Event::Html(o) | Event::InlineHtml(o) => {
+            vec![Event::Text(restore_spoilers(&o).into())]
+        }
+        Event::Code(o) => vec![Event::Code(restore_spoilers(&o).into())],
+        //Images are linked to, not embedded
+        Event::Start(
+            Tag::Link {
+                link_type,
+                dest_url,
+                title,
+                id,
+            }
+            | Tag::Image {
+                link_type,
+                dest_url,
+                title,
+                id,
+            },
+        ) => vec![Event::Start(Tag::Link {
+            link_type,
+            dest_url: safe_url(&dest_url).into(),
+            title,
+            id,
+        })],
+        Event::End(TagEnd::Image) => vec![Event::End(TagEnd::Link)],
+        Event::SoftBreak | Event::HardBreak => {
+            vec![Event::InlineHtml(inline.close_words().into()), event]
+        }
+        //Spoilers and superscripts don't go past the end of a paragraph, a list item, etc.
+        Event::End(
+            o @ (TagEnd::Paragraph
+            | TagEnd::Heading(_)
+            | TagEnd::Item
+            | TagEnd::TableCell
+            | TagEnd::BlockQuote(_)),
+        ) => vec![Event::InlineHtml(inline.close_all().into()), Event::End(o)],
+        o => vec![o],
+    });
+    let mut out = String::new();
+    html::push_html(&mut out, events);
+    out
+}
+
+//Swap the >! and !< of every spoiler for the markers, a spoiler can't span lines
+fn mark_spoilers(text: &str) -> String {
+    text.split('\n')
+        .map(|line| {
+            let mut out = String::with_capacity(line.len());
+            let mut rest = line;
+            while let Some(start) = rest.find(">!") {
+                let Some(len) = rest[start + 2..].find("!<") else {
+                    break;
+                };
+                out += &rest[..start];
+                out.push(SPOILER_START);
+                out += &rest[start + 2..start + 2 + len];
+                out.push(SPOILER_END);
+                rest = &rest[start + 4 + len..];
+            }
+            out + rest
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn restore_spoilers(text: &str) -> String {
+    text.replace(SPOILER_START, ">!").replace(SPOILER_END, "!<")
+}
+
+//Only http(s), mailto and links to reddit (/r/rust) are kept, the rest links to nothing
+pub fn safe_url(url: &str) -> String {
+    let url = url.trim();
+    if url.starts_with('/') && !url.starts_with("//") {
+        return REDDIT_ORIGIN.to_owned() + url;
+    }
+    let scheme = url
+        .split_once(':')
+        .map(|o| o.0.to_lowercase())
+        .unwrap_or_default();
+    if url.starts_with('#') || matches!(scheme.as_str(), "http" | "https" | "mailto") {
+        url.to_owned()
+    } else {
+        String::from("#")
+    }
+}
+
+//Spoilers and superscripts open in the text of a block
+#[derive(Default)]
+struct Inline {
+    spoilers: usize,
+    //true for ^(...), they end on ')', the rest ends on whitespace
+    superscripts: Vec<bool>,
+}
+
+impl Inline {
+    //Escape the text, and render the spoilers and superscripts in it
+    fn render(&mut self, text: &str) -> String {
+        let mut out = String::with_capacity(text.len());
+        let mut chars = text.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '^' if chars.peek().is_some_and(|o| !o.is_whitespace()) => {
+                    let paren = chars.next_if_eq(&'(').is_some();
+                    self.superscripts.push(paren);
+                    out += "<sup>";
+                }
+                ')' if self.superscripts.last() == Some(&true) => {
+                    self.superscripts.pop();
+                    out += "</sup>";
+                }
+                SPOILER_START => {
+                    self.spoilers += 1;
+                    out += "<span class=\"spoiler\">";
+                }
+                SPOILER_END if self.spoilers > 0 => {
+                    self.spoilers -= 1;
+                    out += "</span>";
+                }
+                SPOILER_END => out += "!&lt;",
+                c if c.is_whitespace() => {
+                    out += &self.close_words();
+                    out.push(c);
+                }
+                c => out += &escape_html(c.encode_utf8(&mut [0; 4])),
+            }
+        }
+        out
+    }
+
+    //Close the superscripts of single words
+    fn close_words(&mut self) -> String {
+        let mut out = String::new();
+        while self.superscripts.last() == Some(&false) {
+            self.superscripts.pop();
+            out += "</sup>";
+        }
+        out
+    }
+
+    //Close everything at the end of a block
+    fn close_all(&mut self) -> String {
+        let out = "</sup>".repeat(self.superscripts.len()) + &"</span>".repeat(self.spoilers);
+        self.superscripts.clear();
+        self.spoilers = 0;
+        out
+    }
+}
@@ -0,0 +1,454 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use json::JsonValue;
+use serde::Deserialize;
+
+use crate::{
+    date::{self, DateFormat},
+    markdown,
+    model::{nullable, ElementData},
+    utils::escape_html,
+};
+
+//What a post has besides it's text, recognised from the fields reddit sends for it
+#[derive(Clone, Debug, PartialEq)]
+pub enum Media {
+    Gallery(Vec<GalleryItem>),
+    Poll(Poll),
+    Video(Video),
+    //The post this one was crossposted from
+    Crosspost(Box<Crosspost>),
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct GalleryItem {
+    pub url: String,
+    pub caption: Option<String>,
+    //A link the item points to
+    pub link: Option<String>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct Poll {
+    pub options: Vec<PollOption>,
+    #[serde(deserialize_with = "nullable")]
+    pub total_vote_count: u64,
+    //Milliseconds since the epoch
+    #[serde(rename = "voting_end_timestamp")]
+    pub voting_end: Option<f64>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct PollOption {
+    #[serde(deserialize_with = "nullable")]
+    pub text: String,
+    //Only sent after the poll ends
+    pub vote_count: Option<u64>,
+}
+
+//A v.redd.it video, the reddit_video of the media of a post
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct Video {
+    //Video only, the audio is in a separate stream
+    #[serde(rename = "fallback_url", deserialize_with = "nullable")]
+    pub url: String,
+    pub hls_url: Option<String>,
+    //In seconds
+    pub duration: Option<u64>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    #[serde(deserialize_with = "nullable")]
+    pub is_gif: bool,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Crosspost {
+    pub id: String,
+    pub title: String,
+    pub author: String,
+    pub subreddit: String,
+    pub permalink: String,
+    pub url: String,
+    //Markdown of the selftext
+    pub body: String,
+    pub ups: i64,
+    pub num_comments: Option<usize>,
+    pub created: Option<DateTime<Utc>>,
+    pub media: Option<Media>,
+}
+
+//gallery_data, the order and captions of the items in media_metadata
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct RawGallery {
+    pub items: Vec<RawGalleryItem>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct RawGalleryItem {
+    #[serde(deserialize_with = "nullable")]
+    pub media_id: String,
+    pub caption: Option<String>,
+    pub outbound_url: Option<String>,
+}
+
+//An entry of media_metadata, s is missing while the media is processed or if it failed
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct RawMediaMetadata {
+    //The mime type, ex. image/jpg
+    #[serde(deserialize_with = "nullable")]
+    pub m: String,
+    pub s: Option<RawMediaSource>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct RawMediaSource {
+    //Images have u, animated ones gif and mp4
+    pub u: Option<String>,
+    pub gif: Option<String>,
+    pub mp4: Option<String>,
+    pub x: Option<u32>,
+    pub y: Option<u32>,
+}
+
+//The media of a post, {"reddit_video": {...}}
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct RawMedia {
+    pub reddit_video: Option<Video>,
+}
+
+impl Media {
+    //None for text and link posts
+    pub fn from_data(data: &ElementData) -> Option<Self> {
+        if let Some(o) = data.crosspost_parent_list.first() {
+            return Some(Self::Crosspost(Box::new(Crosspost::from_data(o))));
+        }
+        if let Some(o) = &data.poll_data {
+            return Some(Self::Poll(o.clone()));
+        }
+        if let Some(o) = &data.gallery_data {
+            return Some(Self::Gallery(gallery_items(
+                o,
+                data.media_metadata.as_ref(),
+            )));
+        }
+        data.media
+            .iter()
+            .chain(&data.secure_media)
+            .find_map(|o| o.reddit_video.clone())
+            .map(Self::Video)
+    }
+
+    pub const fn kind(&self) -> &'static str {
+        match self {
+            Self::Gallery(_) => "gallery",
+            Self::Poll(_) => "poll",
+            Self::Video(_) => "video",
+            Self::Crosspost(_) => "crosspost",
+        }
+    }
+
+    //Lines shown under the text in the Default output
+    pub fn to_text(&self, dates: &DateFormat) -> Vec<String> {
+        match self {
+            Self::Gallery(items) => {
+                let mut lines = vec![format!("[gallery, {}]", count(items.len(), "item"))];
+                for (i, item) in items.iter().enumerate() {
+                    let mut line = format!("{}. {}", i + 1, item.url);
+                    if let Some(o) = &item.caption {
+                        line += &format!(" - {o}");
+                    }
+                    if let Some(o) = &item.link {
+                        line += &format!(" ({o})");
+                    }
+                    lines.push(line);
+                }
+                lines
+            }
+            Self::Poll(poll) => {
+                let mut lines = vec![format!("[{}]", poll.label(dates))];
+                lines.extend(
+                    poll.options
+                        .iter()
+                        .map(|o| format!("- {}{}", o.text, poll.votes(o))),
+                );
+                lines
+            }
+            Self::Video(video) => vec![format!("[{}] {}", video.label(), video.url)],
+            Self::Crosspost(post) => {
+                let mut lines = vec![
+                    format!("[{}] {}", post.label(), post.href()),
+                    post.title.clone(),
+                ];
+                lines.extend(
+                    [&post.url, &post.body]
+                        .into_iter()
+                        .filter(|o| !o.is_empty())
+                        .flat_map(|o| o.lines().map(str::to_owned)),
+                );
+                if let Some(o) = &post.media {
+                    lines.extend(o.to_text(dates));
+                }
+                lines
+            }
+        }
+    }
+
+    pub fn to_html(&self, dates: &DateFormat) -> String {
+        let content = match self {
+            Self::Gallery(items) => items
+                .iter()
+                .map(|item| {
+                    let url = safe_url(&item.url);
+                    let caption = item.caption.as_deref().map(escape_html);
+                    let mut figcaption = caption.clone().unwrap_or_default();
+                    if let Some(o) = &item.link {
+                        figcaption += &format!(" <a href=\"{}\">{}</a>", safe_url(o), escape_html(o));
+                    }
+                    if !figcaption.is_empty() {
+                        figcaption = format!("<figcaption>{}</figcaption>", figcaption.trim_start());
+                    }
+                    format!(
+                        "<figure><a href=\"{url}\"><img src=\"{url}\" alt=\"{}\" loading=\"lazy\"></a>{figcaption}</figure>",
+                        caption.unwrap_or_default()
+                    )
+                })
+                .collect::<String>(),
+            Self::Poll(poll) => {
+                let options = poll
+                    .options
+                    .iter()
+                    .map(|o| format!("<li>{}{}</li>", escape_html(&o.text), poll.votes(o)))
+                    .collect::<String>();
+                format!(
+                    "<h5>{}</h5><ul>{options}</ul>",
+                    escape_html(&poll.label(dates))
+                )
+            }
+            Self::Video(video) => {
+                let url = safe_url(&video.url);
+                let mut size = String::new();
+                if let (Some(w), Some(h)) = (video.width, video.height) {
+                    size = format!(" width=\"{w}\" height=\"{h}\"");
+                }
+                format!(
+                    "<h5>{}</h5><video src=\"{url}\"{size} controls preload=\"none\"></video><a href=\"{url}\">{}</a>",
+                    escape_html(&video.label()),
+                    escape_html(&video.url)
+                )
+            }
+            Self::Crosspost(post) => {
+                let mut content = format!(
+                    "<h5><a href=\"{}\">{}</a></h5><h3>{}</h3>",
+                    safe_url(&post.href()),
+                    escape_html(&post.label()),
+                    escape_html(&post.title)
+                );
+                if !post.url.is_empty() && post.url != post.href() {
+                    content += &format!(
+                        "<a href=\"{}\">{}</a>",
+                        safe_url(&post.url),
+                        escape_html(&post.url)
+                    );
+                }
+                content += &markdown::to_html(&post.body);
+                if let Some(o) = &post.media {
+                    content += &o.to_html(dates);
+                }
+                content
+            }
+        };
+        format!("<div class=\"media {}\">{content}</div>", self.kind())
+    }
+
+    pub fn to_json(&self) -> JsonValue {
+        let mut out = match self {
+            Self::Gallery(items) => json::object! {
+                items: items.iter().map(|o| json::object! {
+                    url: o.url.as_str(),
+                    caption: o.caption.as_deref(),
+                    link: o.link.as_deref(),
+                    width: o.width,
+                    height: o.height,
+                }).collect::<Vec<_>>(),
+            },
+            Self::Poll(poll) => json::object! {
+                total_vote_count: poll.total_vote_count,
+                voting_end: poll.voting_end().as_ref().map(date::iso),
+                options: poll.options.iter().map(|o| json::object! {
+                    text: o.text.as_str(),
+                    vote_count: o.vote_count,
+                }).collect::<Vec<_>>(),
+            },
+            Self::Video(video) => json::object! {
+                url: video.url.as_str(),
+                hls_url: video.hls_url.as_deref(),
+                duration: video.duration,
+                width: video.width,
+                height: video.height,
+                is_gif: video.is_gif,
+            },
+            Self::Crosspost(post) => json::object! {
+                id: post.id.as_str(),
+                title: post.title.as_str(),
+                author: post.author.as_str(),
+                subreddit: post.subreddit.as_str(),
+                permalink: post.permalink.as_str(),
+                url: post.url.as_str(),
+                body: post.body.as_str(),
+                ups: post.ups,
+                num_comments: post.num_comments,
+                created: post.created.as_ref().map(date::iso),
+                media: post.media.as_ref().map_or(JsonValue::Null, Self::to_json),
+            },
+        };
+        out["type"] = self.kind().into();
+        out
+    }
+}
+
+impl Poll {
+    pub fn voting_end(&self) -> Option<DateTime<Utc>> {
+        self.voting_end
+            .and_then(|o| date::from_timestamp(o / 1000.0))
+    }
+
+    //ex. poll, 120 votes, ended 2023-01-30 12:34
+    fn label(&self, dates: &DateFormat) -> String {
+        let mut label = format!("poll, {}", count(self.total_vote_count as usize, "vote"));
+        if let Some(o) = self.voting_end() {
+            let ended = dates.now.unwrap_or_else(Utc::now) >= o;
+            label += &format!(
+                ", {} {}",
+                if ended { "ended" } else { "ends" },
+                dates.absolute(&o)
+            );
+        }
+        label
+    }
+
+    //ex. : 12 votes (10%), empty while the poll is open
+    fn votes(&self, option: &PollOption) -> String {
+        let Some(votes) = option.vote_count else {
+            return String::new();
+        };
+        let mut out = format!(": {}", count(votes as usize, "vote"));
+        if self.total_vote_count > 0 {
+            out += &format!(
+                " ({:.0}%)",
+                votes as f64 * 100.0 / self.total_vote_count as f64
+            );
+        }
+        out
+    }
+}
+
+impl Video {
+    //ex. video, 1:05, 1920x1080
+    fn label(&self) -> String {
+        let mut label = String::from(if self.is_gif { "gif" } else { "video" });
+        if let Some(o) = self.duration {
+            label += &format!(", {}:{:02}", o / 60, o % 60);
+        }
+        if let (Some(w), Some(h)) = (self.width, self.height) {
+            label += &format!(", {w}x{h}");
+        }
+        label
+    }
+}
+
+impl Crosspost {
+    fn from_data(data: &ElementData) -> Self {
+        Self {
+            id: data.id.clone(),
+            title: data.title.clone(),
+            author: data.author.clone(),
+            subreddit: data.meta.subreddit.clone(),
+            permalink: data.permalink.clone(),
+            url: data.url.clone(),
+            body: data.selftext.clone(),
+            ups: data.ups,
+            num_comments: data.num_comments,
+            created: data
+                .created_utc
+                .or(data.created)
+                .and_then(date::from_timestamp),
+            media: Media::from_data(data),
+        }
+    }
+
+    pub fn href(&self) -> String {
+        String::from("https://reddit.com") + &self.permalink
+    }
+
+    //ex. crosspost of r/rust by bob, 120 ups, 4 comments
+    fn label(&self) -> String {
+        let mut label = format!(
+            "crosspost of r/{} by {}, {} ups",
+            self.subreddit, self.author, self.ups
+        );
+        if let Some(o) = self.num_comments {
+            label += &format!(", {}", count(o, "comment"));
+        }
+        label
+    }
+}
+
+//The items in the order of gallery_data, with their urls from media_metadata
+fn gallery_items(
+    gallery: &RawGallery,
+    metadata: Option<&HashMap<String, RawMediaMetadata>>,
+) -> Vec<GalleryItem> {
+    gallery
+        .items
+        .iter()
+        .map(|item| {
+            let meta = metadata.and_then(|o| o.get(&item.media_id));
+            let source = meta.and_then(|o| o.s.as_ref());
+            let url = source
+                .and_then(|o| o.u.as_ref().or(o.gif.as_ref()).or(o.mp4.as_ref()))
+                .cloned()
+                .unwrap_or_else(|| {
+                    //Not processed yet, i.redd.it still serves the original
+                    let ext = meta
+                        .and_then(|o| o.m.split_once('/'))
+                        .map_or("jpg", |o| o.1);
+                    format!("https://i.redd.it/{}.{ext}", item.media_id)
+                });
+            GalleryItem {
+                url,
+                caption: item
+                    .caption
+                    .as_deref()
+                    .map(str::to_owned)
+                    .filter(|o| !o.trim().is_empty()),
+                link: item
+                    .outbound_url
+                    .as_deref()
+                    .map(str::to_owned)
+                    .filter(|o| !o.is_empty()),
+                width: source.and_then(|o| o.x),
+                height: source.and_then(|o| o.y),
+            }
+        })
+        .collect()
+}
+
+fn safe_url(url: &str) -> String {
+    escape_html(&markdown::safe_url(url))
+}
+
+//ex. 1 vote, 3 votes
+fn count(n: usize, noun: &str) -> String {
+    format!("{n} {noun}{}", if n == 1 { "" } else { "s" })
+}
@@ -0,0 +1,311 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use json::JsonValue;
+use serde::{Deserialize, Deserializer};
+
+use crate::{
+    date,
+    media::{Poll, RawGallery, RawMedia, RawMediaMetadata},
+};
+
+//The kind of a reddit thing (the prefix of it's fullname, ex. t1_<id>)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+pub enum Kind {
+    #[serde(rename = "t1")]
+    Comment,
+    #[serde(rename = "t2")]
+    Account,
+    #[serde(rename = "t3")]
+    Post,
+    #[serde(rename = "t4")]
+    Message,
+    #[serde(rename = "t5")]
+    Subreddit,
+    #[serde(rename = "t6")]
+    Award,
+    #[serde(rename = "more")]
+    More,
+    #[default]
+    #[serde(other)]
+    Unknown,
+}
+
+impl Kind {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Comment => "t1",
+            Self::Account => "t2",
+            Self::Post => "t3",
+            Self::Message => "t4",
+            Self::Subreddit => "t5",
+            Self::Award => "t6",
+            Self::More => "more",
+            Self::Unknown => "",
+        }
+    }
+}
+
+//Reddit sends false, or the timestamp of the last edit
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(from = "RawEdited")]
+pub enum Edited {
+    #[default]
+    No,
+    //Some old posts only say that they were edited
+    Unknown,
+    At(DateTime<Utc>),
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawEdited {
+    Flag(bool),
+    At(f64),
+}
+
+impl From<RawEdited> for Edited {
+    fn from(value: RawEdited) -> Self {
+        match value {
+            RawEdited::Flag(false) => Self::No,
+            RawEdited::Flag(true) => Self::Unknown,
+            RawEdited::At(o) => date::from_timestamp(o).map_or(Self::Unknown, Self::At),
+        }
+    }
+}
+
+impl Edited {
+    pub const fn is_edited(self) -> bool {
+        !matches!(self, Self::No)
+    }
+
+    //false, true or the date in ISO 8601
+    pub fn to_json(self) -> JsonValue {
+        match self {
+            Self::No => false.into(),
+            Self::Unknown => true.into(),
+            Self::At(o) => date::iso(&o).into(),
+        }
+    }
+
+    //Read a value written by Edited::to_json,
+    //older outputs used timestamps, and usize::MAX for not edited
+    pub fn from_json(value: &JsonValue) -> Self {
+        if let Some(o) = value.as_str() {
+            return DateTime::parse_from_rfc3339(o)
+                .map_or(Self::Unknown, |o| Self::At(o.with_timezone(&Utc)));
+        }
+        match (value.as_bool(), value.as_f64()) {
+            (Some(true), _) => Self::Unknown,
+            (_, Some(o)) => date::from_timestamp(o).map_or(Self::No, Self::At),
+            _ => Self::No,
+        }
+    }
+}
+
+//Whether an element was deleted or removed, and by who
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum State {
+    #[default]
+    Live,
+    //Deleted by it's author, the text and the author are gone
+    Deleted,
+    //Removed by the moderators or reddit, the author may still be there
+    Removed,
+    //The author deleted their account, the text is still there
+    AuthorDeleted,
+}
+
+impl State {
+    //body is the selftext of posts, removed_by_category is only sent for posts
+    pub fn classify(author: &str, body: &str, removed_by_category: Option<&str>) -> Self {
+        match removed_by_category {
+            Some("deleted" | "author") => return Self::Deleted,
+            Some(_) => return Self::Removed,
+            None => {}
+        }
+        match (author, body) {
+            (_, "[removed]") => Self::Removed,
+            (_, "[deleted]") => Self::Deleted,
+            ("[deleted]", _) => Self::AuthorDeleted,
+            _ => Self::Live,
+        }
+    }
+
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Live => "live",
+            Self::Deleted => "deleted",
+            Self::Removed => "removed",
+            Self::AuthorDeleted => "author_deleted",
+        }
+    }
+
+    //Read a value written by State::as_str
+    pub fn parse(value: &str) -> Option<Self> {
+        [
+            Self::Live,
+            Self::Deleted,
+            Self::Removed,
+            Self::AuthorDeleted,
+        ]
+        .into_iter()
+        .find(|o| o.as_str() == value)
+    }
+
+    //Shown next to the author, None for live elements
+    pub const fn label(self) -> Option<&'static str> {
+        match self {
+            Self::Live => None,
+            Self::Deleted => Some("deleted by author"),
+            Self::Removed => Some("removed by moderators"),
+            Self::AuthorDeleted => Some("author deleted"),
+        }
+    }
+
+    pub const fn is_deleted(self) -> bool {
+        !matches!(self, Self::Live)
+    }
+}
+
+//Metadata of posts and comments, fields only one of them has are left empty on the other
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct Metadata {
+    //ups minus downs, fuzzed by reddit
+    #[serde(deserialize_with = "nullable")]
+    pub score: i64,
+    //Posts only
+    pub upvote_ratio: Option<f64>,
+    #[serde(rename = "link_flair_text")]
+    pub link_flair: Option<String>,
+    #[serde(rename = "author_flair_text")]
+    pub author_flair: Option<String>,
+    //moderator, admin or special
+    pub distinguished: Option<String>,
+    #[serde(deserialize_with = "nullable")]
+    pub stickied: bool,
+    #[serde(deserialize_with = "nullable")]
+    pub locked: bool,
+    //The comment was written by the author of the post
+    #[serde(deserialize_with = "nullable")]
+    pub is_submitter: bool,
+    //1 if the comment has about as many downvotes as upvotes
+    #[serde(deserialize_with = "nullable")]
+    pub controversiality: u32,
+    #[serde(rename = "total_awards_received", deserialize_with = "nullable")]
+    pub awards: usize,
+    #[serde(deserialize_with = "nullable")]
+    pub subreddit: String,
+}
+
+impl Metadata {
+    //Flairs without their whitespace, None if they're empty
+    pub fn link_flair(&self) -> Option<&str> {
+        non_empty(self.link_flair.as_deref())
+    }
+
+    pub fn author_flair(&self) -> Option<&str> {
+        non_empty(self.author_flair.as_deref())
+    }
+
+    pub const fn is_controversial(&self) -> bool {
+        self.controversiality > 0
+    }
+}
+
+fn non_empty(value: Option<&str>) -> Option<&str> {
+    value.map(str::trim).filter(|o| !o.is_empty())
+}
+
+//The data of a post or a comment, without it's replies
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct ElementData {
+    #[serde(deserialize_with = "nullable")]
+    pub name: String,
+    #[serde(deserialize_with = "nullable")]
+    pub id: String,
+    //Fullname of the parent, t1_<id> or t3_<id>
+    #[serde(deserialize_with = "nullable")]
+    pub parent_id: String,
+    #[serde(deserialize_with = "nullable")]
+    pub author: String,
+    #[serde(deserialize_with = "nullable")]
+    pub title: String,
+    #[serde(deserialize_with = "nullable")]
+    pub url: String,
+    #[serde(deserialize_with = "nullable")]
+    pub url_overridden_by_dest: String,
+    #[serde(deserialize_with = "nullable")]
+    pub selftext: String,
+    #[serde(deserialize_with = "nullable")]
+    pub body: String,
+    #[serde(deserialize_with = "nullable")]
+    pub permalink: String,
+    pub num_comments: Option<usize>,
+    #[serde(deserialize_with = "nullable")]
+    pub ups: i64,
+    #[serde(deserialize_with = "nullable")]
+    pub depth: usize,
+    #[serde(deserialize_with = "nullable")]
+    pub over_18: bool,
+    //created is the same on new posts, but was in the local time of reddit's servers on old ones
+    pub created_utc: Option<f64>,
+    pub created: Option<f64>,
+    #[serde(deserialize_with = "nullable")]
+    pub edited: Edited,
+    //Why a post is gone, ex. deleted, moderator, reddit
+    pub removed_by_category: Option<String>,
+    //Galleries, the items are in media_metadata
+    pub gallery_data: Option<RawGallery>,
+    //Media by id, of galleries and images in comments
+    pub media_metadata: Option<HashMap<String, RawMediaMetadata>>,
+    pub poll_data: Option<Poll>,
+    //The post a crosspost is of
+    #[serde(deserialize_with = "nullable")]
+    pub crosspost_parent_list: Vec<ElementData>,
+    //Videos, secure_media is the same with https urls
+    pub media: Option<RawMedia>,
+    pub secure_media: Option<RawMedia>,
+    #[serde(flatten)]
+    pub meta: Metadata,
+}
+
+//A listing child, {"kind": ..., "data": {...}}
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct Thing {
+    pub kind: Kind,
+    pub data: ElementData,
+}
+
+impl Thing {
+    //Read as much as possible of a child that doesn't deserialize as a whole,
+    //the fields that don't are left out, so one bad field doesn't lose the element
+    pub fn lenient(child: &serde_json::Value) -> Self {
+        let fields = child["data"]
+            .as_object()
+            .into_iter()
+            .flatten()
+            .filter(|(key, value)| {
+                let field = serde_json::Map::from_iter([((*key).clone(), (*value).clone())]);
+                ElementData::deserialize(&serde_json::Value::Object(field)).is_ok()
+            })
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect::<serde_json::Map<_, _>>();
+        Self {
+            kind: Kind::deserialize(&child["kind"]).unwrap_or_default(),
+            data: ElementData::deserialize(&serde_json::Value::Object(fields)).unwrap_or_default(),
+        }
+    }
+}
+
+//Reddit sends null for a lot of empty fields
+pub fn nullable<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de> + Default,
+{
+    Ok(Option::<T>::deserialize(deserializer)?.unwrap_or_default())
+}
@@ -0,0 +1,324 @@
+use std::{future::Future, sync::Arc, sync::Mutex, time::SystemTime};
+
+use tokio::task::JoinSet;
+
+use crate::{
+    cli::{Verbosity, CLI},
+    element::Element,
+    error::SrpError,
+    session::FetchSession,
+    tree::TreeBuilder,
+    utils::{self, print_progress},
+};
+
+//Max number of ids the morechildren api accepts in one call
+pub const MORE_CHILDREN_LIMIT: usize = 100;
+
+//Request every element listed by the 'more' elements of the session, and append them to the tree
+//The ids are requested in batches through the morechildren api,
+//'more' elements found in the results are expanded the same way until none are left.
+//Ids from batches that failed are requested one by one.
+//Reply chains cut off with 'continue this thread' are requested through the permalink of their parent.
+//At most cli.jobs requests run at once, failed requests are stored in the session.
+pub async fn fetch_more(
+    cli: &CLI,
+    session: &Arc<FetchSession>,
+    elements: Vec<Element>,
+) -> Result<Vec<Element>, SrpError> {
+    if (session.more_elements_count() == 0 && session.continue_stubs().is_empty())
+        || session.reached_max()
+    {
+        return Ok(elements);
+    }
+    if !cli.req_more_elements {
+        cli.print_infom("Not requesting more elements, because of --no-more-elements flag");
+        return Ok(elements);
+    }
+
+    let more_elems_dir_path = utils::TMP_DIR.to_owned() + "more_elements";
+    if cli.save_tmp_files {
+        std::fs::create_dir_all(&more_elems_dir_path)?;
+    }
+
+    cli.print_infom("Getting 'more' elements:");
+    cli.print_info(format!("Running {} jobs", cli.jobs));
+
+    let link_id = session.link_id();
+    let mut tree = TreeBuilder::new(&link_id);
+    session.sub_elements_count(tree.extend_roots(elements));
+    if link_id.is_empty() {
+        cli.print_warning("Failed to get the post's id, requesting 'more' elements one by one");
+    }
+    let mut cursor = Cursor::default();
+    loop {
+        let failed = if link_id.is_empty() {
+            let listed = session.more_elements()[cursor.more..].to_vec();
+            cursor.more += listed.len();
+            listed.into_iter().filter(|o| !session.is_done(o)).collect()
+        } else {
+            fetch_more_children(
+                cli,
+                session,
+                &mut tree,
+                &link_id,
+                &mut cursor,
+                &more_elems_dir_path,
+            )
+            .await
+        };
+
+        if !failed.is_empty() {
+            fetch_more_one_by_one(cli, session, &mut tree, failed, &more_elems_dir_path).await;
+        }
+
+        let stubs = session.continue_stubs()[cursor.stubs..].to_vec();
+        cursor.stubs += stubs.len();
+        let mut stubs = stubs
+            .into_iter()
+            .filter(|o| !session.is_done(o) && !session.is_continued(o))
+            .collect::<Vec<_>>();
+        stubs.sort();
+        stubs.dedup();
+        if session.reached_max() || stubs.is_empty() && cursor.more >= session.more_elements_len() {
+            break;
+        }
+        if stubs.is_empty() {
+            continue;
+        }
+
+        if cli.verbosity != Verbosity::Low {
+            println!();
+        }
+        cli.print_infom(format!(
+            "Following {} 'continue this thread' link{}:",
+            stubs.len(),
+            if stubs.len() == 1 { "" } else { "s" }
+        ));
+        for id in fetch_more_one_by_one(cli, session, &mut tree, stubs, &more_elems_dir_path).await
+        {
+            session.push_continued(id);
+        }
+    }
+
+    if cli.verbosity != Verbosity::Low {
+        println!();
+    }
+    let failed = session.failed();
+    if !failed.is_empty() {
+        cli.print_warning(format!(
+            "{} request{} failed:",
+            failed.len(),
+            if failed.len() == 1 { "" } else { "s" }
+        ));
+        for (ids, e) in failed {
+            cli.print_warning(format!("{}: {e}", ids.join(",")));
+        }
+    }
+    Ok(build_tree(cli, session, tree))
+}
+
+//Put the tree together, and warn about orphans
+pub(crate) fn build_tree(cli: &CLI, session: &FetchSession, tree: TreeBuilder) -> Vec<Element> {
+    let (elements, orphans) = tree.build();
+    if !orphans.is_empty() {
+        cli.print_warning(format!(
+            "The parents of {} element{} were never fetched, they were put at the top level: {}",
+            orphans.len(),
+            if orphans.len() == 1 { "" } else { "s" },
+            orphans.join(",")
+        ));
+    }
+    session.set_orphans(orphans);
+    elements
+}
+
+//Run every task, with at most `jobs` of them running at once.
+//on_done is called (on the calling task) with the output of every task as soon as it finishes.
+pub(crate) async fn run_bounded<T, F>(
+    cli: &CLI,
+    jobs: usize,
+    tasks: impl IntoIterator<Item = F>,
+    mut on_done: impl FnMut(T),
+) where
+    F: Future<Output = T> + Send + 'static,
+    T: Send + 'static,
+{
+    let mut join_set = JoinSet::new();
+    let mut handle = |res: Result<T, tokio::task::JoinError>| match res {
+        Ok(o) => on_done(o),
+        Err(e) => cli.print_warning(format!("A task failed: {e}")),
+    };
+    for task in tasks {
+        while join_set.len() >= jobs.max(1) {
+            if let Some(res) = join_set.join_next().await {
+                handle(res);
+            }
+        }
+        join_set.spawn(task);
+    }
+    while let Some(res) = join_set.join_next().await {
+        handle(res);
+    }
+}
+
+//How far fetch_more got, kept between it's rounds
+#[derive(Default)]
+struct Cursor {
+    //Number of ids from session.more_elements already requested
+    more: usize,
+    //Number of session.continue_stubs already followed
+    stubs: usize,
+    //Index of the next morechildren_N.json file
+    batch_idx: usize,
+}
+
+//Expand 'more' elements through the morechildren api
+//Returns the ids that failed to be requested
+async fn fetch_more_children(
+    cli: &CLI,
+    session: &Arc<FetchSession>,
+    tree: &mut TreeBuilder,
+    link_id: &str,
+    cursor: &mut Cursor,
+    more_elems_dir_path: &str,
+) -> Vec<String> {
+    let more_start = SystemTime::now();
+    let last_line_length = Mutex::new(0usize);
+    let origin = utils::url_origin(&cli.base_url);
+
+    let mut failed = Vec::new();
+    //Ids requested by an earlier run count as done
+    let mut requested = session.checkpoint.as_ref().map_or(0, |o| o.done_len()) + cursor.more;
+    loop {
+        let listed = session.more_elements()[cursor.more..].to_vec();
+        if listed.is_empty() || session.reached_max() {
+            break;
+        }
+        cursor.more += listed.len();
+        let pending = listed
+            .into_iter()
+            .filter(|o| !session.is_done(o))
+            .collect::<Vec<_>>();
+
+        let tasks = pending
+            .chunks(MORE_CHILDREN_LIMIT)
+            .map(<[String]>::to_vec)
+            .map(|batch| {
+                let (session, origin, link_id) =
+                    (Arc::clone(session), origin.clone(), link_id.to_owned());
+                async move {
+                    if session.reached_max() {
+                        return (batch, Ok(None));
+                    }
+                    let res = Element::get_more_children(&origin, &link_id, &batch, &session)
+                        .await
+                        .map(Some);
+                    (batch, res)
+                }
+            })
+            .collect::<Vec<_>>();
+
+        run_bounded(cli, cli.jobs, tasks, |(batch, res)| {
+            match res {
+                Ok(Some((new_elements, data))) => {
+                    session.sub_elements_count(tree.extend(new_elements));
+                    if let Some(checkpoint) = &session.checkpoint {
+                        if let Err(e) = checkpoint.save_batch(&batch, &data) {
+                            cli.print_warning(format!("Saving the checkpoint failed: {e}"));
+                        }
+                    }
+                    if cli.save_tmp_files {
+                        let path = format!(
+                            "{more_elems_dir_path}/morechildren_{}.json",
+                            cursor.batch_idx
+                        );
+                        if let Err(e) = std::fs::write(path, data) {
+                            cli.print_warning(SrpError::from(e));
+                        }
+                    }
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    cli.print_warning(format!("Getting a batch of 'more' elements failed: {e}"));
+                    failed.extend_from_slice(&batch);
+                }
+            }
+            cursor.batch_idx += 1;
+            requested += batch.len();
+            print_progress(
+                &cli.verbosity,
+                cli.print_timestamps,
+                requested as f64,
+                session.more_elements_len(),
+                more_start,
+                &last_line_length,
+            );
+        })
+        .await;
+    }
+    failed
+}
+
+//Request every id separately, using the post's permalink
+//Returns the ids that were requested successfully
+async fn fetch_more_one_by_one(
+    cli: &CLI,
+    session: &Arc<FetchSession>,
+    tree: &mut TreeBuilder,
+    ids: Vec<String>,
+    more_elems_dir_path: &str,
+) -> Vec<String> {
+    let more_start = SystemTime::now();
+    let last_line_length = Mutex::new(0usize);
+    let total = ids.len();
+    let mut idx = 0usize;
+    let mut succeeded = Vec::new();
+
+    let tasks = ids
+        .into_iter()
+        .map(|id| {
+            let url = cli.base_url.clone() + &id + ".json?raw_json=1";
+            let session = Arc::clone(session);
+            async move {
+                if session.reached_max() {
+                    return (id, Ok(None));
+                }
+                let res = Element::get_more_element(&url, &session).await.map(Some);
+                (id, res)
+            }
+        })
+        .collect::<Vec<_>>();
+
+    run_bounded(cli, cli.jobs, tasks, |(id, res)| {
+        match res {
+            Ok(Some((new_elements, data))) => {
+                session.sub_elements_count(tree.extend(new_elements));
+                if let Some(checkpoint) = &session.checkpoint {
+                    if let Err(e) = checkpoint.save_single(&id, &data) {
+                        cli.print_warning(format!("Saving the checkpoint failed: {e}"));
+                    }
+                }
+                if cli.save_tmp_files {
+                    let path = format!("{more_elems_dir_path}/{id}.json");
+                    if let Err(e) = std::fs::write(path, data) {
+                        cli.print_warning(SrpError::from(e));
+                    }
+                }
+                succeeded.push(id);
+            }
+            Ok(None) => {}
+            Err(e) => session.push_failed(vec![id], e),
+        }
+        idx += 1;
+        print_progress(
+            &cli.verbosity,
+            cli.print_timestamps,
+            idx as f64,
+            total,
+            more_start,
+            &last_line_length,
+        );
+    })
+    .await;
+    succeeded
+}
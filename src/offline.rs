@@ -0,0 +1,119 @@
+use std::{
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    cli::CLI,
+    element::Element,
+    error::SrpError,
+    more,
+    session::FetchSession,
+    tree::{self, TreeBuilder},
+    url::REDDIT_ORIGIN,
+};
+
+//Rebuild the element tree from saved data, without any network access.
+//input is either a directory written with --save-tmp (raw.json and more_elements/),
+//a raw json file of a post or "-" for stdin.
+//Returns the elements and the url of the post.
+pub fn load(
+    cli: &CLI,
+    session: &FetchSession,
+    input: &str,
+) -> Result<(Vec<Element>, String), SrpError> {
+    let dir = Path::new(input);
+    let raw = if input == "-" {
+        let mut raw = String::new();
+        std::io::stdin().read_to_string(&mut raw)?;
+        raw
+    } else if dir.is_dir() {
+        read(&dir.join("raw.json"))?
+    } else {
+        read(dir)?
+    };
+
+    let json_data = serde_json::from_str(&raw)?;
+    cli.print_info("Parsing to JSON: success");
+    let origin = cli.host.as_deref().unwrap_or(REDDIT_ORIGIN);
+    let source = post_url(&json_data, origin);
+    let mut elements = crate::parse(json_data, session)?;
+    cli.print_info("Parsing to elements: success.");
+
+    let more_elems_dir = dir.join("more_elements");
+    if input != "-" && more_elems_dir.is_dir() {
+        elements = load_more_elements(cli, session, elements, &more_elems_dir)?;
+    }
+
+    let ids = tree::collect_ids(&elements);
+    let not_saved = session
+        .more_elements()
+        .iter()
+        .filter(|o| !ids.contains(o.as_str()))
+        .count();
+    if not_saved != 0 && !session.reached_max() {
+        cli.print_warning(format!(
+            "{not_saved} 'more' elements listed in the data weren't saved, they are missing"
+        ));
+    }
+
+    Ok((elements, source))
+}
+
+//Merge the saved 'more' elements responses into the tree,
+//morechildren_N.json files in the order they were requested, then every {id}.json file
+pub(crate) fn load_more_elements(
+    cli: &CLI,
+    session: &FetchSession,
+    elements: Vec<Element>,
+    dir: &Path,
+) -> Result<Vec<Element>, SrpError> {
+    let mut batches = Vec::<(usize, PathBuf)>::new();
+    let mut singles = Vec::<PathBuf>::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        let Some(name) = path.file_name().and_then(|o| o.to_str()) else {
+            continue;
+        };
+        let Some(stem) = name.strip_suffix(".json") else {
+            continue;
+        };
+        match stem
+            .strip_prefix("morechildren_")
+            .and_then(|o| o.parse::<usize>().ok())
+        {
+            Some(idx) => batches.push((idx, path)),
+            None => singles.push(path),
+        }
+    }
+    batches.sort_by_key(|o| o.0);
+    singles.sort();
+
+    let mut tree = TreeBuilder::new(&session.link_id());
+    session.sub_elements_count(tree.extend_roots(elements));
+    for (_, path) in batches {
+        let json_data = serde_json::from_str(&read(&path)?)?;
+        match Element::parse_more_children(json_data, session) {
+            Ok(new_elements) => session.sub_elements_count(tree.extend(new_elements)),
+            Err(e) => cli.print_warning(format!("{}: {e}", path.display())),
+        }
+    }
+    for path in singles {
+        let json_data = serde_json::from_str(&read(&path)?)?;
+        session.sub_elements_count(tree.extend(Element::init(json_data, session)));
+    }
+    Ok(more::build_tree(cli, session, tree))
+}
+
+pub(crate) fn read(path: &Path) -> Result<String, SrpError> {
+    std::fs::read_to_string(path)
+        .map_err(|e| SrpError::Filesystem(format!("Failed to read {}: {e}", path.display())))
+}
+
+//Url of the post in json_data, used as the source of the output
+fn post_url(json_data: &serde_json::Value, origin: &str) -> String {
+    let permalink = json_data[0]["data"]["children"][0]["data"]["permalink"]
+        .as_str()
+        .unwrap_or("/");
+    origin.to_owned() + permalink
+}
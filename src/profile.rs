@@ -0,0 +1,251 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+    time::SystemTime,
+};
+
+use json::JsonValue;
+
+use crate::{
+    cli::CLI,
+    element::Element,
+    error::SrpError,
+    listing::{fetch_pages, PAGE_LIMIT},
+    scheduler::RequestScheduler,
+    session::FetchSession,
+    utils,
+};
+
+//A user's profile, ex. reddit.com/user/spez, reddit.com/u/spez/comments
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Profile {
+    pub user: String,
+    pub submitted: bool,
+    pub comments: bool,
+    //Max number of submissions and of comments to archive
+    pub limit: usize,
+}
+
+impl Profile {
+    //Parse a profile url, None if it isn't one
+    pub fn from_url(url: &str) -> Option<Self> {
+        let url = url.trim();
+        let path = url.split_once('?').map_or(url, |o| o.0);
+        let path = path.trim_end_matches('/').trim_end_matches(".json");
+        let mut segments = path
+            .split('/')
+            .skip_while(|o| *o != "user" && *o != "u")
+            .skip(1)
+            .filter(|o| !o.is_empty());
+        let user = segments.next()?;
+        let (submitted, comments) = match segments.next() {
+            None | Some("overview") => (true, true),
+            Some("submitted") => (true, false),
+            Some("comments") => (false, true),
+            _ => return None,
+        };
+        if segments.next().is_some() {
+            return None;
+        }
+        Some(Self {
+            user: user.to_owned(),
+            submitted,
+            comments,
+            limit: usize::MAX,
+        })
+    }
+
+    //Url of the user's page, ex. https://www.reddit.com/user/spez/
+    pub fn base_url(&self, origin: &str) -> String {
+        format!("{origin}/user/{}/", self.user)
+    }
+
+    //Request the submissions and comments, and build the elements:
+    //a header element, then every submission, then every comment under it's context
+    //(the post it was made on and the parent comment, if any).
+    //Comments on the same post share it's element
+    pub async fn fetch(
+        &self,
+        cli: &CLI,
+        session: &FetchSession,
+        origin: &str,
+    ) -> Result<Vec<Element>, SrpError> {
+        let header = json::object! {
+            kind: "t2",
+            data: {
+                name: format!("t2_{}", self.user),
+                id: self.user.clone(),
+                author: self.user.clone(),
+                title: format!("Submissions and comments of u/{}", self.user),
+                permalink: format!("/user/{}/", self.user),
+            }
+        };
+        let mut elements = Vec::from_iter(Element::create(&header, session));
+
+        if self.submitted {
+            let submitted = self
+                .fetch_kind(cli, &session.scheduler, origin, "submitted")
+                .await?;
+            cli.print_infom(format!("Got {} submissions", submitted.len()));
+            elements.extend(submitted.iter().filter_map(|o| Element::create(o, session)));
+        }
+
+        if self.comments {
+            let mut comments = self
+                .fetch_kind(cli, &session.scheduler, origin, "comments")
+                .await?;
+            //Pages can overlap if the user commented while paging
+            let mut seen = HashSet::new();
+            comments.retain(|o| seen.insert(o["data"]["name"].to_string()));
+            cli.print_infom(format!("Got {} comments", comments.len()));
+            let parents = fetch_parents(cli, &session.scheduler, origin, &comments).await;
+            for comment in &comments {
+                add_with_context(&mut elements, comment, &parents, session);
+            }
+        }
+        Ok(elements)
+    }
+
+    //Page through /user/<name>/<kind>
+    async fn fetch_kind(
+        &self,
+        cli: &CLI,
+        scheduler: &RequestScheduler,
+        origin: &str,
+        kind: &str,
+    ) -> Result<Vec<JsonValue>, SrpError> {
+        fetch_pages(cli, scheduler, self.limit, |after, limit| {
+            let mut url = format!(
+                "{origin}/user/{}/{kind}.json?raw_json=1&limit={limit}",
+                self.user
+            );
+            if let Some(after) = after {
+                url += &format!("&after={after}");
+            }
+            url
+        })
+        .await
+    }
+}
+
+//Request the parent comments of the comments through /api/info, by fullname
+//Failures only cost the context, so they are just warned about
+async fn fetch_parents(
+    cli: &CLI,
+    scheduler: &RequestScheduler,
+    origin: &str,
+    comments: &[JsonValue],
+) -> HashMap<String, JsonValue> {
+    let mut ids = comments
+        .iter()
+        .filter_map(|o| o["data"]["parent_id"].as_str())
+        .filter(|o| o.starts_with("t1_"))
+        .collect::<Vec<_>>();
+    ids.sort_unstable();
+    ids.dedup();
+
+    let mut parents = HashMap::new();
+    for batch in ids.chunks(PAGE_LIMIT) {
+        let url = format!("{origin}/api/info.json?raw_json=1&id={}", batch.join(","));
+        let res = match scheduler.request(&url).await {
+            Ok(o) => o.text().await.map_err(SrpError::from),
+            Err(e) => Err(e),
+        };
+        match res.and_then(|o| json::parse(&o).map_err(SrpError::from)) {
+            Ok(mut json_data) => {
+                for child in json_data["data"]["children"].members_mut() {
+                    if let Some(name) = child["data"]["name"].as_str() {
+                        parents.insert(name.to_owned(), child.take());
+                    }
+                }
+            }
+            Err(e) => cli.print_warning(format!("Getting the parent comments failed: {e}")),
+        }
+    }
+    parents
+}
+
+//Add the comment under the post it was made on and the parent comment (if any),
+//reusing their elements if another comment already added them
+fn add_with_context(
+    elements: &mut Vec<Element>,
+    comment: &JsonValue,
+    parents: &HashMap<String, JsonValue>,
+    session: &FetchSession,
+) {
+    let data = &comment["data"];
+    let link_id = data["link_id"].as_str().unwrap_or_default();
+    let post_id = link_id.get(3..).unwrap_or_default();
+
+    //The first element is the header
+    let i = match elements.iter().skip(1).position(|o| o.id == post_id) {
+        Some(i) => i + 1,
+        None => {
+            let post = json::object! {
+                kind: "t3",
+                data: {
+                    name: link_id,
+                    id: post_id,
+                    title: data["link_title"].clone(),
+                    author: data["link_author"].clone(),
+                    permalink: data["link_permalink"].clone(),
+                    depth: 0,
+                }
+            };
+            let Some(post) = Element::create(&post, session) else {
+                return;
+            };
+            elements.push(post);
+            elements.len() - 1
+        }
+    };
+    let post = &mut elements[i];
+
+    let parent = data["parent_id"].as_str().and_then(|id| {
+        let parent = parents.get(id)?;
+        let parent_id = parent["data"]["id"].as_str()?;
+        if let Some(i) = post.children.iter().position(|o| o.id == parent_id) {
+            return Some(i);
+        }
+        let mut parent = parent.clone();
+        parent["data"]["depth"] = 1.into();
+        parent["data"]["replies"] = JsonValue::Null;
+        post.children.push(Element::create(&parent, session)?);
+        Some(post.children.len() - 1)
+    });
+
+    let mut comment = comment.clone();
+    comment["data"]["depth"] = (1 + usize::from(parent.is_some())).into();
+    comment["data"]["replies"] = JsonValue::Null;
+    let Some(comment) = Element::create(&comment, session) else {
+        return;
+    };
+
+    match parent {
+        Some(i) => post.children[i].children.push(comment),
+        None => post.children.push(comment),
+    }
+}
+
+//Archive the profile to the output specified by the cli
+pub async fn archive(cli: &CLI, profile: &Profile) -> Result<(), SrpError> {
+    let start = SystemTime::now();
+
+    let scheduler = Arc::new(RequestScheduler::new(cli.client.build(&cli.url)?));
+    let session = FetchSession::new(cli.format, cli.max_comments).with_scheduler(scheduler);
+    let origin = utils::url_origin(&cli.base_url);
+
+    cli.print_infom(format!("Getting the profile of u/{}:", profile.user));
+    let elements = profile.fetch(cli, &session, &origin).await?;
+    if elements.len() < 2 {
+        return Err(SrpError::JsonParse(format!(
+            "u/{} has no submissions or comments",
+            profile.user
+        )));
+    }
+
+    crate::warn_malformed(cli, &session);
+
+    let elements = utils::sort_elements_(elements, cli)?;
+    utils::write_to_output(cli, &session, &elements, start)
+}
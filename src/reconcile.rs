@@ -0,0 +1,135 @@
+use std::collections::HashSet;
+
+use json::JsonValue;
+
+use crate::{cli::CLI, element::Element, session::FetchSession, tree};
+
+//Explains the difference between the number of comments reported by reddit
+//and the number of comments that were fetched
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Report {
+    //num_comments of the post
+    pub reported: usize,
+    //Comments in the tree
+    pub fetched: usize,
+    //Listed by 'more' elements, but never returned by reddit
+    pub never_returned: Vec<String>,
+    //Listed by 'more' elements, but not requested (--no-more-elements or --max reached)
+    pub not_requested: Vec<String>,
+    //Ids of requests that failed
+    pub failed: Vec<String>,
+    //Parent ids of 'continue this thread' stubs that weren't followed
+    pub continue_stubs: Vec<String>,
+    //Deleted or removed comments, they are counted in fetched
+    pub deleted: Vec<String>,
+    //Comments whose parent was never fetched, they are counted in fetched
+    pub orphans: Vec<String>,
+}
+
+impl Report {
+    //Reconcile the tree (before filtering) with the state of the session
+    pub fn new(cli: &CLI, session: &FetchSession, elements: &[Element]) -> Self {
+        let ids = tree::collect_ids(elements);
+
+        let mut fetched = 0;
+        let mut deleted = Vec::new();
+        let mut stack = elements.iter().collect::<Vec<_>>();
+        while let Some(element) = stack.pop() {
+            if !element.is_post() {
+                fetched += 1;
+            }
+            if element.is_deleted() {
+                deleted.push(element.id.clone());
+            }
+            stack.extend(&element.children);
+        }
+        deleted.sort();
+
+        let failed = session
+            .failed()
+            .into_iter()
+            .flat_map(|o| o.0)
+            .filter(|o| !ids.contains(o.as_str()))
+            .collect::<Vec<_>>();
+        let failed_ids = failed.iter().map(String::as_str).collect::<HashSet<_>>();
+
+        //Ids the crawl got to, the rest wasn't requested
+        let requested = cli.req_more_elements && !session.reached_max();
+        let (never_returned, not_requested) = session
+            .more_elements()
+            .into_iter()
+            .filter(|o| !ids.contains(o.as_str()) && !failed_ids.contains(o.as_str()))
+            .partition(|o| requested || session.is_done(o));
+
+        Self {
+            reported: session.num_comments(),
+            fetched,
+            never_returned,
+            not_requested,
+            failed,
+            continue_stubs: session
+                .continue_stubs()
+                .into_iter()
+                .filter(|o| !session.is_continued(o) && !session.is_done(o))
+                .collect(),
+            deleted,
+            orphans: session.orphans(),
+        }
+    }
+
+    pub fn difference(&self) -> usize {
+        self.reported.abs_diff(self.fetched)
+    }
+
+    pub fn to_json(&self) -> JsonValue {
+        json::object! {
+            reported: self.reported,
+            fetched: self.fetched,
+            difference: self.difference(),
+            never_returned: self.never_returned.clone(),
+            not_requested: self.not_requested.clone(),
+            failed: self.failed.clone(),
+            continue_stubs: self.continue_stubs.clone(),
+            deleted: self.deleted.clone(),
+            orphans: self.orphans.clone(),
+        }
+    }
+
+    //Print the report, the details only if there is a difference
+    pub fn print(&self, cli: &CLI) {
+        cli.print_infom(format!(
+            "Comments reported: {}, fetched: {}, difference: {}",
+            self.reported,
+            self.fetched,
+            self.difference()
+        ));
+        if self.difference() == 0 {
+            return;
+        }
+        let lines = [
+            (
+                self.never_returned.len(),
+                "listed in 'more' elements, but never returned",
+            ),
+            (
+                self.not_requested.len(),
+                "listed in 'more' elements, but not requested",
+            ),
+            (self.failed.len(), "in failed requests"),
+            (
+                self.continue_stubs.len(),
+                "'continue this thread' stubs, not expanded",
+            ),
+            (
+                self.deleted.len(),
+                "deleted or removed (counted as fetched)",
+            ),
+            (self.orphans.len(), "orphans (counted as fetched)"),
+        ];
+        for (n, what) in lines {
+            if n != 0 {
+                cli.print_infom(format!("  {n} {what}"));
+            }
+        }
+    }
+}
@@ -0,0 +1,176 @@
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use rand::Rng;
+use reqwest::{header::HeaderMap, StatusCode};
+
+use crate::{client::ClientConfig, error::SrpError};
+
+//Max number of retries of a single request
+const MAX_RETRIES: u32 = 5;
+//Delay before the first retry, doubled with every next one
+const BASE_DELAY: Duration = Duration::from_millis(500);
+const MAX_DELAY: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Default)]
+struct RateLimitState {
+    //Requests left in the current window (X-Ratelimit-Remaining)
+    remaining: Option<f64>,
+    //When the current window ends (X-Ratelimit-Reset)
+    reset_at: Option<Instant>,
+    //No request can be sent before this (Retry-After or backoff after a 429)
+    blocked_until: Option<Instant>,
+    //Total time the requests were blocked for
+    throttled: Duration,
+}
+
+impl RateLimitState {
+    fn block_until(&mut self, until: Instant) {
+        let blocked_from = self
+            .blocked_until
+            .map_or_else(Instant::now, |o| o.max(Instant::now()));
+        if until > blocked_from {
+            self.throttled += until - blocked_from;
+            self.blocked_until = Some(until);
+        }
+    }
+}
+
+//Sends every request of a run, so all of them respect reddit's rate limits.
+//Can be shared (behind an Arc) by many sessions.
+#[derive(Debug)]
+pub struct RequestScheduler {
+    //Shared by every request, so connections are reused
+    client: reqwest::Client,
+    state: Mutex<RateLimitState>,
+}
+
+impl Default for RequestScheduler {
+    fn default() -> Self {
+        Self::new(
+            ClientConfig::default()
+                .build("")
+                .unwrap_or_else(|_| reqwest::Client::new()),
+        )
+    }
+}
+
+impl RequestScheduler {
+    pub fn new(client: reqwest::Client) -> Self {
+        Self {
+            client,
+            state: Mutex::new(RateLimitState::default()),
+        }
+    }
+
+    //How long requests were held back because of rate limiting
+    pub fn throttled(&self) -> Duration {
+        self.lock().throttled
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, RateLimitState> {
+        self.state
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+
+    //Block every request until `until`, and count the time added to the block
+    fn block_until(&self, until: Instant) {
+        self.lock().block_until(until);
+    }
+
+    //Wait until a request can be sent
+    async fn wait(&self) {
+        loop {
+            let now = Instant::now();
+            let wait_until = {
+                let mut state = self.lock();
+                //Used up all requests in this window, wait for the next one
+                if let (Some(remaining), Some(reset_at)) = (state.remaining, state.reset_at) {
+                    if remaining < 1.0 && reset_at > now {
+                        state.block_until(reset_at);
+                    }
+                }
+                let wait_until = state.blocked_until.filter(|o| *o > now);
+                //This request is going to be sent, count it
+                if wait_until.is_none() {
+                    state.remaining = state.remaining.map(|o| o - 1.0);
+                }
+                wait_until
+            };
+            match wait_until {
+                Some(o) => tokio::time::sleep_until(o.into()).await,
+                None => return,
+            }
+        }
+    }
+
+    //Read the rate limit headers of a response
+    fn update(&self, headers: &HeaderMap) {
+        let header = |name: &str| {
+            headers
+                .get(name)
+                .and_then(|o| o.to_str().ok())
+                .and_then(|o| o.trim().parse::<f64>().ok())
+        };
+        let mut state = self.lock();
+        if let Some(remaining) = header("x-ratelimit-remaining") {
+            state.remaining = Some(remaining);
+        }
+        if let Some(reset) = header("x-ratelimit-reset") {
+            state.reset_at = Some(Instant::now() + Duration::from_secs_f64(reset.max(0.0)));
+        }
+    }
+
+    //Exponential backoff with jitter for the given retry
+    pub fn backoff(retry: u32) -> Duration {
+        let delay = BASE_DELAY
+            .saturating_mul(2u32.saturating_pow(retry))
+            .min(MAX_DELAY);
+        let jitter = rand::thread_rng().gen_range(0.0..=0.5);
+        delay + delay.mul_f64(jitter)
+    }
+
+    //Send a GET request to url, waiting for the rate limit and retrying on failure
+    pub async fn request(&self, url: &str) -> Result<reqwest::Response, SrpError> {
+        let mut retry = 0;
+        loop {
+            self.wait().await;
+            let err = match self.client.get(url).send().await {
+                Ok(o) => {
+                    self.update(o.headers());
+                    let status = o.status();
+                    if status.is_success() {
+                        return Ok(o);
+                    }
+                    if status != StatusCode::TOO_MANY_REQUESTS && !status.is_server_error() {
+                        return Err(SrpError::HttpStatus(status.as_u16(), url.to_owned()));
+                    }
+                    //Rate limited or server error, hold back every request for a while
+                    let retry_after = o
+                        .headers()
+                        .get(reqwest::header::RETRY_AFTER)
+                        .and_then(|o| o.to_str().ok())
+                        .and_then(|o| o.trim().parse::<u64>().ok())
+                        .map(Duration::from_secs);
+                    self.block_until(
+                        Instant::now() + retry_after.unwrap_or_else(|| Self::backoff(retry)),
+                    );
+                    SrpError::HttpStatus(status.as_u16(), url.to_owned())
+                }
+                Err(e) => {
+                    if retry < MAX_RETRIES {
+                        tokio::time::sleep(Self::backoff(retry)).await;
+                    }
+                    SrpError::Network(e.to_string())
+                }
+            };
+            if retry >= MAX_RETRIES {
+                return Err(err);
+            }
+            retry += 1;
+        }
+    }
+}
@@ -0,0 +1,217 @@
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc, Mutex,
+};
+
+use crate::{
+    checkpoint::Checkpoint, element::Format, error::SrpError, scheduler::RequestScheduler,
+};
+
+//State of a single archiving run.
+//Shared (behind an Arc) by every task working on the same post,
+//so one process can archive many posts at the same time.
+#[derive(Debug)]
+pub struct FetchSession {
+    pub format: Format,
+    pub max_comments: usize,
+    //Sends every request of this session, can be shared with other sessions
+    pub scheduler: Arc<RequestScheduler>,
+    //Where the progress of the 'more' elements crawl is saved, if anywhere
+    pub checkpoint: Option<Checkpoint>,
+    num_comments: AtomicUsize,
+    elements_count: AtomicUsize,
+    more_elements_count: AtomicUsize,
+    more_elements: Mutex<Vec<String>>,
+    //Fullname (t3_<id>) of the post, needed by the morechildren api
+    link_id: Mutex<String>,
+    //Requests that failed, with the ids they were supposed to get
+    failed: Mutex<Vec<(Vec<String>, SrpError)>>,
+    //Parent ids of 'continue this thread' stubs ('more' elements with count 0)
+    continue_stubs: Mutex<Vec<String>>,
+    //Parent ids of the stubs that were followed
+    continued: Mutex<Vec<String>>,
+    //Elements whose parent was never fetched
+    orphans: Mutex<Vec<String>>,
+    //Elements that were only partly parsed, with the error
+    malformed: Mutex<Vec<(String, String)>>,
+}
+
+impl FetchSession {
+    pub fn new(format: Format, max_comments: usize) -> Self {
+        Self {
+            format,
+            max_comments,
+            scheduler: Arc::new(RequestScheduler::default()),
+            checkpoint: None,
+            num_comments: AtomicUsize::new(0),
+            //Starts at 1, the post itself is counted as an element
+            elements_count: AtomicUsize::new(1),
+            more_elements_count: AtomicUsize::new(0),
+            more_elements: Mutex::new(Vec::new()),
+            link_id: Mutex::new(String::new()),
+            failed: Mutex::new(Vec::new()),
+            continue_stubs: Mutex::new(Vec::new()),
+            continued: Mutex::new(Vec::new()),
+            orphans: Mutex::new(Vec::new()),
+            malformed: Mutex::new(Vec::new()),
+        }
+    }
+
+    //Use a scheduler shared with other sessions,
+    //so all of them respect the same rate limit
+    pub fn with_scheduler(mut self, scheduler: Arc<RequestScheduler>) -> Self {
+        self.scheduler = scheduler;
+        self
+    }
+
+    pub fn with_checkpoint(mut self, checkpoint: Checkpoint) -> Self {
+        self.checkpoint = Some(checkpoint);
+        self
+    }
+
+    //True if the id was already requested in an earlier (resumed) run
+    pub fn is_done(&self, id: &str) -> bool {
+        self.checkpoint.as_ref().is_some_and(|o| o.is_done(id))
+    }
+
+    //Number of comments reported by reddit for the post
+    pub fn num_comments(&self) -> usize {
+        self.num_comments.load(Ordering::SeqCst)
+    }
+
+    pub fn set_num_comments(&self, n: usize) {
+        self.num_comments.store(n, Ordering::SeqCst);
+    }
+
+    pub fn elements_count(&self) -> usize {
+        self.elements_count.load(Ordering::SeqCst)
+    }
+
+    pub fn inc_elements_count(&self) {
+        self.elements_count.fetch_add(1, Ordering::SeqCst);
+    }
+
+    pub fn sub_elements_count(&self, n: usize) {
+        self.elements_count.fetch_sub(n, Ordering::SeqCst);
+    }
+
+    //True if no more elements should be parsed
+    pub fn reached_max(&self) -> bool {
+        self.elements_count() >= self.max_comments
+    }
+
+    pub fn more_elements_count(&self) -> usize {
+        self.more_elements_count.load(Ordering::SeqCst)
+    }
+
+    pub fn add_more_elements_count(&self, n: usize) {
+        self.more_elements_count.fetch_add(n, Ordering::SeqCst);
+    }
+
+    pub fn push_more_element(&self, id: String) {
+        self.more_elements
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .push(id);
+    }
+
+    //Snapshot of the ids listed by 'more' elements so far
+    pub fn more_elements(&self) -> Vec<String> {
+        self.more_elements
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .clone()
+    }
+
+    pub fn more_elements_len(&self) -> usize {
+        self.more_elements
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .len()
+    }
+
+    pub fn link_id(&self) -> String {
+        self.link_id
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .clone()
+    }
+
+    pub fn set_link_id(&self, link_id: String) {
+        *self
+            .link_id
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner) = link_id;
+    }
+
+    pub fn push_failed(&self, ids: Vec<String>, err: SrpError) {
+        self.failed
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .push((ids, err));
+    }
+
+    pub fn failed(&self) -> Vec<(Vec<String>, SrpError)> {
+        self.failed
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .clone()
+    }
+
+    pub fn push_continue_stub(&self, parent_id: String) {
+        self.continue_stubs
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .push(parent_id);
+    }
+
+    pub fn continue_stubs(&self) -> Vec<String> {
+        self.continue_stubs
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .clone()
+    }
+
+    pub fn push_continued(&self, parent_id: String) {
+        self.continued
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .push(parent_id);
+    }
+
+    pub fn is_continued(&self, parent_id: &str) -> bool {
+        self.continued
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .iter()
+            .any(|o| o == parent_id)
+    }
+
+    pub fn set_orphans(&self, orphans: Vec<String>) {
+        *self
+            .orphans
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner) = orphans;
+    }
+
+    pub fn orphans(&self) -> Vec<String> {
+        self.orphans
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .clone()
+    }
+
+    pub fn push_malformed(&self, id: String, err: String) {
+        self.malformed
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .push((id, err));
+    }
+
+    pub fn malformed(&self) -> Vec<(String, String)> {
+        self.malformed
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .clone()
+    }
+}
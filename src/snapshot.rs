@@ -0,0 +1,274 @@
+use std::{collections::HashMap, fmt::Display};
+
+use json::JsonValue;
+
+use crate::{
+    element::Element,
+    error::SrpError,
+    model::{Edited, State},
+};
+
+//The state of one element at the time of a snapshot
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Entry {
+    pub parent_id: String,
+    pub author: String,
+    pub data: String,
+    pub ups: i64,
+    pub edited: Edited,
+    pub deleted: bool,
+}
+
+//Every element of a post at one point in time, by id
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Snapshot {
+    entries: HashMap<String, Entry>,
+    //Ids in the order of the tree, so changes are listed in a stable order
+    order: Vec<String>,
+}
+
+//A difference between two snapshots of the same post
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Change {
+    Added {
+        id: String,
+        parent_id: String,
+        author: String,
+    },
+    //The text changed, or the edited timestamp did
+    Edited {
+        id: String,
+        author: String,
+        old: String,
+        new: String,
+    },
+    Score {
+        id: String,
+        author: String,
+        old: i64,
+        new: i64,
+    },
+    //Deleted by it's author or removed by a moderator
+    Deleted {
+        id: String,
+        author: String,
+    },
+    //Not returned anymore, reddit drops deleted comments without replies
+    Missing {
+        id: String,
+        author: String,
+    },
+}
+
+impl Change {
+    pub const fn kind(&self) -> &'static str {
+        match self {
+            Self::Added { .. } => "added",
+            Self::Edited { .. } => "edited",
+            Self::Score { .. } => "score",
+            Self::Deleted { .. } => "deleted",
+            Self::Missing { .. } => "missing",
+        }
+    }
+
+    //Id of the element that changed
+    pub fn id(&self) -> &str {
+        match self {
+            Self::Added { id, .. }
+            | Self::Edited { id, .. }
+            | Self::Score { id, .. }
+            | Self::Deleted { id, .. }
+            | Self::Missing { id, .. } => id,
+        }
+    }
+
+    pub fn author(&self) -> &str {
+        match self {
+            Self::Added { author, .. }
+            | Self::Edited { author, .. }
+            | Self::Score { author, .. }
+            | Self::Deleted { author, .. }
+            | Self::Missing { author, .. } => author,
+        }
+    }
+
+    //Score changes don't count as activity, they go on long after a thread is quiet
+    pub const fn is_activity(&self) -> bool {
+        !matches!(self, Self::Score { .. })
+    }
+}
+
+impl Display for Change {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Added {
+                id,
+                parent_id,
+                author,
+            } => write!(f, "+ new {id} by {author} (reply to {parent_id})"),
+            Self::Edited { id, author, .. } => write!(f, "~ edited {id} by {author}"),
+            Self::Score {
+                id,
+                author,
+                old,
+                new,
+            } => write!(f, "^ score {id} by {author}: {old} -> {new}"),
+            Self::Deleted { id, author } => write!(f, "- deleted {id} by {author}"),
+            Self::Missing { id, author } => write!(f, "- missing {id} by {author}"),
+        }
+    }
+}
+
+impl Snapshot {
+    pub fn new(elements: &[Element]) -> Self {
+        let mut snapshot = Self::default();
+        let mut stack = elements.iter().rev().collect::<Vec<_>>();
+        while let Some(element) = stack.pop() {
+            snapshot.insert(
+                element.id.clone(),
+                Entry {
+                    parent_id: element.parent_id.clone(),
+                    author: element.author.clone(),
+                    data: element.data().to_owned(),
+                    ups: element.ups,
+                    edited: element.edited,
+                    deleted: element.is_deleted(),
+                },
+            );
+            stack.extend(element.children.iter().rev());
+        }
+        snapshot
+    }
+
+    //Load a snapshot from a json output of srp (see Format::JSON)
+    pub fn from_output(json_data: &JsonValue) -> Result<Self, SrpError> {
+        let top = &json_data["data"];
+        if !top.is_array() {
+            return Err(SrpError::JsonParse(String::from(
+                "Not a json output of srp, the data array is missing",
+            )));
+        }
+        //Replies to the post are next to it, not under it
+        let post_id = top
+            .members()
+            .find(|o| o["kind"] == "t3")
+            .and_then(|o| o["id"].as_str())
+            .unwrap_or_default();
+
+        let mut snapshot = Self::default();
+        let mut stack = top
+            .members()
+            .rev()
+            .map(|o| (o, if o["kind"] == "t3" { "" } else { post_id }))
+            .collect::<Vec<_>>();
+        while let Some((element, parent_id)) = stack.pop() {
+            let Some(id) = element["id"].as_str() else {
+                continue;
+            };
+            let kind = element["kind"].as_str().unwrap_or_default();
+            let author = element["author"].as_str().unwrap_or_default();
+            let data = element["data"].as_str().unwrap_or_default();
+            //Older outputs don't have the state
+            let state = element["state"]
+                .as_str()
+                .and_then(State::parse)
+                .unwrap_or_else(|| State::classify(author, data, None));
+            snapshot.insert(
+                id.to_owned(),
+                Entry {
+                    parent_id: parent_id.to_owned(),
+                    author: author.to_owned(),
+                    data: data.to_owned(),
+                    ups: element["ups"].as_i64().unwrap_or(0),
+                    edited: Edited::from_json(&element["edited"]),
+                    deleted: kind == "t1" && state.is_deleted(),
+                },
+            );
+            //Older outputs only kept the last child, as an object
+            let children = &element["children"];
+            if children.is_object() {
+                stack.push((children, id));
+            } else {
+                stack.extend(children.members().rev().map(|o| (o, id)));
+            }
+        }
+        Ok(snapshot)
+    }
+
+    pub fn insert(&mut self, id: String, entry: Entry) {
+        if self.entries.insert(id.clone(), entry).is_none() {
+            self.order.push(id);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get(&self, id: &str) -> Option<&Entry> {
+        self.entries.get(id)
+    }
+
+    //Changes from self to newer
+    pub fn changes(&self, newer: &Self) -> Vec<Change> {
+        let mut changes = Vec::new();
+        for id in &newer.order {
+            let new = &newer.entries[id];
+            let Some(old) = self.entries.get(id) else {
+                changes.push(Change::Added {
+                    id: id.clone(),
+                    parent_id: new.parent_id.clone(),
+                    author: new.author.clone(),
+                });
+                continue;
+            };
+            //Deleted comments lose their author, so keep the old one
+            if new.deleted && !old.deleted {
+                changes.push(Change::Deleted {
+                    id: id.clone(),
+                    author: old.author.clone(),
+                });
+            } else if old.data != new.data || old.edited != new.edited {
+                changes.push(Change::Edited {
+                    id: id.clone(),
+                    author: new.author.clone(),
+                    old: old.data.clone(),
+                    new: new.data.clone(),
+                });
+            }
+            if old.ups != new.ups {
+                changes.push(Change::Score {
+                    id: id.clone(),
+                    author: new.author.clone(),
+                    old: old.ups,
+                    new: new.ups,
+                });
+            }
+        }
+        for id in &self.order {
+            if !newer.entries.contains_key(id) {
+                changes.push(Change::Missing {
+                    id: id.clone(),
+                    author: self.entries[id].author.clone(),
+                });
+            }
+        }
+        changes
+    }
+}
+
+//ex. 3 new, 1 edited, 2 deleted, 5 score changes
+pub fn summary(changes: &[Change]) -> String {
+    let count = |f: fn(&Change) -> bool| changes.iter().filter(|o| f(o)).count();
+    format!(
+        "{} new, {} edited, {} deleted, {} score changes",
+        count(|o| matches!(o, Change::Added { .. })),
+        count(|o| matches!(o, Change::Edited { .. })),
+        count(|o| matches!(o, Change::Deleted { .. } | Change::Missing { .. })),
+        count(|o| matches!(o, Change::Score { .. })),
+    )
+}
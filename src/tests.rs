@@ -1,12 +1,14 @@
 #![cfg(test)]
-use rand::{distributions::Alphanumeric, prelude::*};
-
-#[path = "cli.rs"]
-mod cli;
-use cli::CLI;
+use rand::prelude::*;
 
 use crate::{
-    element::{Element, FORMAT},
+    cli::{self, CLI},
+    client::ClientConfig,
+    date::{DateFormat, Timezone},
+    element::{Element, Format},
+    error::SrpError,
+    model::{Edited, Kind},
+    session::FetchSession,
     utils,
 };
 
@@ -14,21 +16,28 @@ fn st(x: &str) -> String {
     x.into()
 }
 
+//Dates in utc, relative to a fixed time, so outputs don't depend on the machine or the day
+fn test_dates() -> DateFormat {
+    DateFormat {
+        timezone: Timezone::Utc,
+        now: chrono::DateTime::from_timestamp(1_675_209_600, 0),
+        ..Default::default()
+    }
+}
+
 const CLI_ELEMENT_FILTER_DEF: cli::ElementFilter = cli::ElementFilter::Default;
 const CLI_ELEMENT_SORT_DEF: cli::ElementSort = cli::ElementSort::Default;
 
 //TODO: add more tests
 #[test]
 fn test_element() {
-    unsafe {
-        crate::element::FORMAT = crate::element::Format::Default;
-    }
     let data = include_str!("element_test_data1.json");
     let test_file_path = "test-output.tmp";
 
-    let json_data = json::parse(data).unwrap();
+    let json_data = serde_json::from_str::<serde_json::Value>(data).unwrap();
 
-    let elements = crate::element::Element::init(&json_data, usize::MAX);
+    let session = FetchSession::new(Format::Default, usize::MAX);
+    let elements = crate::element::Element::init(json_data, &session);
     let mut output = std::fs::OpenOptions::new()
         .create(true)
         .write(true)
@@ -36,7 +45,13 @@ fn test_element() {
         .open(test_file_path)
         .unwrap();
     for elem in elements {
-        match std::io::Write::write_fmt(&mut output, format_args!("{elem}")) {
+        match std::io::Write::write_fmt(
+            &mut output,
+            format_args!(
+                "{}",
+                elem.formatted(session.format).with_dates(&test_dates())
+            ),
+        ) {
             Ok(()) => {}
             Err(e) => panic!("Failed to write to output!\nError: {e}"),
         }
@@ -54,19 +69,50 @@ fn test_element() {
 fn test_cli() {
     //This test is incomplete!
 
-    CLI::new(&[st("test-bin"), st("-h")]);
-    let cli1 = CLI::new(&[st("test-bin"), st("https://reddit.com/r/asd")]);
+    assert!(CLI::is_help(&[st("test-bin"), st("-h")]));
+    assert!(CLI::is_help(&[
+        st("test-bin"),
+        st("-o"),
+        st("--help"),
+        st("url")
+    ]));
+    assert!(!CLI::is_help(&[
+        st("test-bin"),
+        st("https://reddit.com/r/asd")
+    ]));
+    //Invalid usage is an error, not an exit
+    assert!(matches!(
+        CLI::new(&[st("test-bin")]),
+        Err(SrpError::Validation(_))
+    ));
+    assert!(matches!(
+        CLI::new(&[
+            st("test-bin"),
+            st("-f"),
+            st("pdf"),
+            st("https://reddit.com/r/asd")
+        ]),
+        Err(SrpError::Validation(_))
+    ));
+    assert!(matches!(
+        CLI::new(&[st("test-bin"), st("not a url")]),
+        Err(SrpError::Validation(_))
+    ));
+    let cli1 = CLI::new(&[st("test-bin"), st("https://reddit.com/r/asd")]).unwrap();
     assert_eq!(
         (cli1.url, cli1.base_url),
         (
-            st("https://reddit.com/r/asd.json"),
-            st("https://reddit.com/r/asd/")
+            st("https://www.reddit.com/r/asd/"),
+            st("https://www.reddit.com/r/asd/")
         )
     );
-    let cli2 = CLI::new(&[st("test-bin"), st("https://reddit.com/r/")]);
+    let cli2 = CLI::new(&[st("test-bin"), st("https://redd.it/abc")]).unwrap();
     assert_eq!(
         (cli2.url, cli2.base_url),
-        (st("https://reddit.com/r.json"), st("https://reddit.com/r/"))
+        (
+            st("https://www.reddit.com/comments/abc.json?raw_json=1"),
+            st("https://www.reddit.com/comments/abc/_/")
+        )
     );
     assert_eq!(
         CLI::new(&[
@@ -74,12 +120,15 @@ fn test_cli() {
             st("-s"),
             st("test-path.txt"),
             st("https://reddit.com/r/asd")
-        ]),
+        ])
+        .unwrap(),
         CLI {
-            url: st("https://reddit.com/r/asd.json"),
-            base_url: st("https://reddit.com/r/asd/"),
+            url: st("https://www.reddit.com/r/asd/"),
+            base_url: st("https://www.reddit.com/r/asd/"),
             save_to_file: true,
             save_path: st("test-path.txt"),
+            format: Format::Default,
+            dates: DateFormat::default(),
             max_comments: usize::MAX,
             filter: CLI_ELEMENT_FILTER_DEF,
             sort_style: CLI_ELEMENT_SORT_DEF,
@@ -88,6 +137,19 @@ fn test_cli() {
             req_more_elements: true,
             delete_tmp: false,
             print_timestamps: false,
+            jobs: cli::default_jobs(),
+            client: ClientConfig::default(),
+            host: None,
+            offline: false,
+            resume: false,
+            report_path: None,
+            batch: false,
+            //A subreddit url is archived as a listing
+            listing: crate::listing::Listing::from_url("https://reddit.com/r/asd"),
+            profile: None,
+            context: None,
+            watch: None,
+            diff: None,
         }
     );
     assert_eq!(
@@ -96,13 +158,16 @@ fn test_cli() {
             st("-o"),
             st("-s"),
             st("test-path.txt"),
-            st("https://reddit.com/r/")
-        ]),
+            st("https://reddit.com/r/foo/comments/abc/")
+        ])
+        .unwrap(),
         CLI {
-            url: st("https://reddit.com/r.json"),
-            base_url: st("https://reddit.com/r/"),
+            url: st("https://www.reddit.com/r/foo/comments/abc.json?raw_json=1"),
+            base_url: st("https://www.reddit.com/r/foo/comments/abc/_/"),
             save_to_file: false,
             save_path: st("test-path.txt"),
+            format: Format::Default,
+            dates: DateFormat::default(),
             max_comments: usize::MAX,
             filter: CLI_ELEMENT_FILTER_DEF,
             sort_style: CLI_ELEMENT_SORT_DEF,
@@ -111,15 +176,34 @@ fn test_cli() {
             req_more_elements: true,
             delete_tmp: false,
             print_timestamps: false,
+            jobs: cli::default_jobs(),
+            client: ClientConfig::default(),
+            host: None,
+            offline: false,
+            resume: false,
+            report_path: None,
+            batch: false,
+            listing: None,
+            profile: None,
+            context: None,
+            watch: None,
+            diff: None,
         }
     );
     assert_eq!(
-        CLI::new(&[st("test-bin"), st("-o"), st("https://reddit.com/r/")]),
+        CLI::new(&[
+            st("test-bin"),
+            st("-o"),
+            st("https://reddit.com/r/foo/comments/abc/")
+        ])
+        .unwrap(),
         CLI {
-            url: st("https://reddit.com/r.json"),
-            base_url: st("https://reddit.com/r/"),
+            url: st("https://www.reddit.com/r/foo/comments/abc.json?raw_json=1"),
+            base_url: st("https://www.reddit.com/r/foo/comments/abc/_/"),
             save_to_file: false,
             save_path: st("output.txt"),
+            format: Format::Default,
+            dates: DateFormat::default(),
             max_comments: usize::MAX,
             filter: CLI_ELEMENT_FILTER_DEF,
             sort_style: CLI_ELEMENT_SORT_DEF,
@@ -128,21 +212,59 @@ fn test_cli() {
             req_more_elements: true,
             delete_tmp: false,
             print_timestamps: false,
+            jobs: cli::default_jobs(),
+            client: ClientConfig::default(),
+            host: None,
+            offline: false,
+            resume: false,
+            report_path: None,
+            batch: false,
+            listing: None,
+            profile: None,
+            context: None,
+            watch: None,
+            diff: None,
         }
     );
 
+    assert_eq!(
+        CLI::new(&[
+            st("test-bin"),
+            st("-j"),
+            st("3"),
+            st("https://reddit.com/r/foo/comments/abc/")
+        ])
+        .unwrap()
+        .jobs,
+        3
+    );
+    assert_eq!(
+        CLI::new(&[
+            st("test-bin"),
+            st("--jobs"),
+            st("0"),
+            st("https://reddit.com/r/foo/comments/abc/")
+        ])
+        .unwrap()
+        .jobs,
+        1
+    );
+
     assert_eq!(
         CLI::new(&[
             st("test-bin"),
             st("-o"),
             st("--save-tmp"),
-            st("https://reddit.com/r/")
-        ]),
+            st("https://reddit.com/r/foo/comments/abc/")
+        ])
+        .unwrap(),
         CLI {
-            url: st("https://reddit.com/r.json"),
-            base_url: st("https://reddit.com/r/"),
+            url: st("https://www.reddit.com/r/foo/comments/abc.json?raw_json=1"),
+            base_url: st("https://www.reddit.com/r/foo/comments/abc/_/"),
             save_to_file: false,
             save_path: st("output.txt"),
+            format: Format::Default,
+            dates: DateFormat::default(),
             max_comments: usize::MAX,
             filter: CLI_ELEMENT_FILTER_DEF,
             sort_style: CLI_ELEMENT_SORT_DEF,
@@ -151,50 +273,202 @@ fn test_cli() {
             req_more_elements: true,
             delete_tmp: false,
             print_timestamps: false,
+            jobs: cli::default_jobs(),
+            client: ClientConfig::default(),
+            host: None,
+            offline: false,
+            resume: false,
+            report_path: None,
+            batch: false,
+            listing: None,
+            profile: None,
+            context: None,
+            watch: None,
+            diff: None,
         }
     );
 }
 
 #[test]
 fn test_cli_parse_url() {
+    let post = || {
+        Ok((
+            st("https://www.reddit.com/r/asd/comments/abc/title.json?raw_json=1"),
+            st("https://www.reddit.com/r/asd/comments/abc/title/"),
+        ))
+    };
     assert_eq!(
-        CLI::parse_url(st("https://reddit.com/r/asd")),
-        (
-            st("https://reddit.com/r/asd.json"),
-            st("https://reddit.com/r/asd/")
-        )
+        CLI::parse_url(st("https://reddit.com/r/asd/comments/abc/title")),
+        post()
     );
     assert_eq!(
-        CLI::parse_url(st("https://reddit.com/r/asd/?foo&bar:443")),
-        (
-            st("https://reddit.com/r/asd.json"),
-            st("https://reddit.com/r/asd/")
-        )
+        CLI::parse_url(st(
+            "https://old.reddit.com/r/asd/comments/abc/title/?foo&bar:443"
+        )),
+        post()
+    );
+    assert_eq!(
+        CLI::parse_url(st("\nnp.reddit.com/r/asd/comments/abc/title.json\n\n ")),
+        post()
+    );
+    assert_eq!(
+        CLI::parse_url(st("https://new.reddit.com:443/r/asd/comments/abc/title/")),
+        post()
+    );
+    assert_eq!(
+        CLI::parse_url(st("https://m.reddit.com/comments/abc")),
+        Ok((
+            st("https://www.reddit.com/comments/abc.json?raw_json=1"),
+            st("https://www.reddit.com/comments/abc/_/")
+        ))
+    );
+    //Comment permalinks
+    assert_eq!(
+        CLI::parse_url(st(
+            "https://www.reddit.com/r/asd/comments/abc/title/def/?context=3"
+        )),
+        Ok((
+            st("https://www.reddit.com/r/asd/comments/abc/title/def.json?raw_json=1&context=3"),
+            st("https://www.reddit.com/r/asd/comments/abc/title/")
+        ))
+    );
+    assert_eq!(
+        CLI::parse_url(st("https://www.reddit.com/user/bob/comments/abc/title")),
+        Ok((
+            st("https://www.reddit.com/r/u_bob/comments/abc/title.json?raw_json=1"),
+            st("https://www.reddit.com/r/u_bob/comments/abc/title/")
+        ))
+    );
+
+    //Test invalid params
+    assert_eq!(
+        CLI::parse_url(st("https://example.com/r/asd/comments/abc")),
+        Err(SrpError::Validation(st(
+            "Invalid url: https://example.com/r/asd/comments/abc"
+        )))
+    );
+    assert!(CLI::parse_url(st("https://reddit.com/r/asd")).is_err());
+    assert!(CLI::parse_url(st("https://reddit.com/r/")).is_err());
+    assert!(CLI::parse_url(st("https://reddit.com/user/asd")).is_err());
+    assert!(CLI::parse_url(st("https://notreddit.com/comments/abc")).is_err());
+    assert!(CLI::parse_url(st("https://reddit.com/r/asd/comments/a-b")).is_err());
+    assert!(CLI::parse_url(st("https://i.redd.it/abc.jpg")).is_err());
+}
+
+#[test]
+fn test_reddit_url() {
+    use crate::url::{RedditUrl, UrlTarget};
+
+    assert_eq!(
+        RedditUrl::parse("https://redd.it/AbC1", None),
+        Ok(RedditUrl {
+            origin: st("https://www.reddit.com"),
+            subreddit: None,
+            target: UrlTarget::Post {
+                id: st("abc1"),
+                slug: None,
+                comment: None,
+                context: None
+            }
+        })
     );
     assert_eq!(
-        CLI::parse_url(st("https://reddit.com/r/asd:443")),
+        RedditUrl::parse("https://www.reddit.com/gallery/abc", None).map(|o| o.urls()),
+        Ok((
+            st("https://www.reddit.com/comments/abc.json?raw_json=1"),
+            st("https://www.reddit.com/comments/abc/_/")
+        ))
+    );
+    let share = RedditUrl::parse("https://www.reddit.com/r/foo/s/Xy1Z2", None).unwrap();
+    assert!(share.is_share() && share.post_id().is_none());
+    assert_eq!(share.urls().0, st("https://www.reddit.com/r/foo/s/Xy1Z2"));
+}
+
+#[tokio::test]
+async fn test_reddit_url_resolve_share() {
+    use crate::{scheduler::RequestScheduler, url::RedditUrl};
+
+    let origin = mock_server(vec![
         (
-            st("https://reddit.com/r/asd.json"),
-            st("https://reddit.com/r/asd/")
-        )
+            "/r/foo/s/token",
+            st("redirect /r/foo/comments/abc/title/?share_id=1"),
+        ),
+        ("/r/foo/comments/abc/title/", st("<html></html>")),
+        ("/r/foo/s/loop2", st("<html></html>")),
+        ("/r/foo/s/loop", st("redirect /r/foo/s/loop2")),
+    ])
+    .await;
+    let scheduler = RequestScheduler::default();
+
+    let share = RedditUrl::parse(&(origin.clone() + "/r/foo/s/token"), Some(&origin)).unwrap();
+    assert_eq!(
+        share
+            .resolve(&scheduler, Some(&origin))
+            .await
+            .map(|o| o.urls()),
+        Ok((
+            origin.clone() + "/r/foo/comments/abc/title.json?raw_json=1",
+            origin.clone() + "/r/foo/comments/abc/title/"
+        ))
+    );
+    let share = RedditUrl::parse("https://reddit.com/r/foo/s/loop", Some(&origin)).unwrap();
+    assert!(share.resolve(&scheduler, Some(&origin)).await.is_err());
+}
+
+#[test]
+fn test_cli_parse_host() {
+    assert_eq!(
+        CLI::parse_host("old.reddit.com/"),
+        Ok(st("https://old.reddit.com"))
     );
     assert_eq!(
-        CLI::parse_url(st("https://reddit.com/r/:443")),
-        (st("https://reddit.com/r.json"), st("https://reddit.com/r/"))
+        CLI::parse_host("http://127.0.0.1:8080"),
+        Ok(st("http://127.0.0.1:8080"))
     );
+    assert!(CLI::parse_host("ftp://127.0.0.1").is_err());
+    assert!(CLI::parse_host("https://example.com/foo").is_err());
+    assert!(CLI::parse_host("").is_err());
+
     assert_eq!(
-        CLI::parse_url(st("https://reddit.com/r/\n\n\n ")),
-        (st("https://reddit.com/r.json"), st("https://reddit.com/r/"))
+        CLI::parse_url_with_host(
+            st("https://www.reddit.com/r/foo/comments/abc/title/?utm=1"),
+            Some("http://127.0.0.1:8080")
+        ),
+        Ok((
+            st("http://127.0.0.1:8080/r/foo/comments/abc/title.json?raw_json=1"),
+            st("http://127.0.0.1:8080/r/foo/comments/abc/title/")
+        ))
     );
+    //Urls of the host itself are valid too
     assert_eq!(
-        CLI::parse_url(st("\nhttps://reddit.com/r/\n\n\n ")),
-        (st("https://reddit.com/r.json"), st("https://reddit.com/r/"))
+        CLI::parse_url_with_host(
+            st("http://127.0.0.1:8080/r/foo/comments/abc/"),
+            Some("127.0.0.1:8080")
+        ),
+        Ok((
+            st("https://127.0.0.1:8080/r/foo/comments/abc.json?raw_json=1"),
+            st("https://127.0.0.1:8080/r/foo/comments/abc/_/")
+        ))
     );
+    assert!(CLI::parse_url_with_host(
+        st("http://127.0.0.1:8080/r/foo/comments/abc/"),
+        Some("http://localhost:8080")
+    )
+    .is_err());
+
+    let cli = CLI::new(&[
+        st("test-bin"),
+        st("--host"),
+        st("old.reddit.com"),
+        st("reddit.com/r/foo/comments/abc"),
+    ])
+    .unwrap();
+    assert_eq!(cli.host, Some(st("https://old.reddit.com")));
     assert_eq!(
-        CLI::parse_url(st("\nhttps://reddit.com/r//foo\n")),
+        (cli.url, cli.base_url),
         (
-            st("https://reddit.com/r//foo.json"),
-            st("https://reddit.com/r//foo/")
+            st("https://old.reddit.com/r/foo/comments/abc.json?raw_json=1"),
+            st("https://old.reddit.com/r/foo/comments/abc/_/")
         )
     );
 }
@@ -203,123 +477,141 @@ fn test_cli_parse_url() {
 fn test_cli_parse_sort_style() {
     //Test valid params
     assert_eq!(
-        cli::CLI::parse_sort_style("default"),
+        cli::CLI::parse_sort_style("default").unwrap(),
         cli::ElementSort::Default
     );
-    assert_eq!(cli::CLI::parse_sort_style("rand"), cli::ElementSort::Rand);
-    assert_eq!(cli::CLI::parse_sort_style("random"), cli::ElementSort::Rand);
     assert_eq!(
-        cli::CLI::parse_sort_style("upvotes"),
+        cli::CLI::parse_sort_style("rand").unwrap(),
+        cli::ElementSort::Rand
+    );
+    assert_eq!(
+        cli::CLI::parse_sort_style("random").unwrap(),
+        cli::ElementSort::Rand
+    );
+    assert_eq!(
+        cli::CLI::parse_sort_style("upvotes").unwrap(),
         cli::ElementSort::Upvotes(false)
     );
     assert_eq!(
-        cli::CLI::parse_sort_style("ups"),
+        cli::CLI::parse_sort_style("ups").unwrap(),
         cli::ElementSort::Upvotes(false)
     );
     assert_eq!(
-        cli::CLI::parse_sort_style("upvotes-asc"),
+        cli::CLI::parse_sort_style("upvotes-asc").unwrap(),
         cli::ElementSort::Upvotes(true)
     );
     assert_eq!(
-        cli::CLI::parse_sort_style("ups-asc"),
+        cli::CLI::parse_sort_style("ups-asc").unwrap(),
         cli::ElementSort::Upvotes(true)
     );
     assert_eq!(
-        cli::CLI::parse_sort_style("comments"),
+        cli::CLI::parse_sort_style("comments").unwrap(),
         cli::ElementSort::Comments(false)
     );
     assert_eq!(
-        cli::CLI::parse_sort_style("comments-asc"),
+        cli::CLI::parse_sort_style("comments-asc").unwrap(),
         cli::ElementSort::Comments(true)
     );
     assert_eq!(
-        cli::CLI::parse_sort_style("new"),
+        cli::CLI::parse_sort_style("new").unwrap(),
         cli::ElementSort::Date(false)
     );
     assert_eq!(
-        cli::CLI::parse_sort_style("old"),
+        cli::CLI::parse_sort_style("old").unwrap(),
         cli::ElementSort::Date(true)
     );
     assert_eq!(
-        cli::CLI::parse_sort_style("edited"),
+        cli::CLI::parse_sort_style("edited").unwrap(),
         cli::ElementSort::EditedDate(false)
     );
     assert_eq!(
-        cli::CLI::parse_sort_style("edited-asc"),
+        cli::CLI::parse_sort_style("edited-asc").unwrap(),
         cli::ElementSort::EditedDate(true)
     );
 
+    assert_eq!(
+        cli::CLI::parse_sort_style("awards").unwrap(),
+        cli::ElementSort::Awards(false)
+    );
+    assert_eq!(
+        cli::CLI::parse_sort_style("awards-asc").unwrap(),
+        cli::ElementSort::Awards(true)
+    );
+    assert_eq!(
+        cli::CLI::parse_sort_style("controversial").unwrap(),
+        cli::ElementSort::Controversial
+    );
+
     //Test edge cases
     assert_eq!(
-        cli::CLI::parse_sort_style("DefaulT"),
+        cli::CLI::parse_sort_style("DefaulT").unwrap(),
         cli::ElementSort::Default
     );
     assert_eq!(
-        cli::CLI::parse_sort_style("Edited "),
+        cli::CLI::parse_sort_style("Edited ").unwrap(),
         cli::ElementSort::EditedDate(false)
     );
     assert_eq!(
-        cli::CLI::parse_sort_style("     eDiTeD"),
+        cli::CLI::parse_sort_style("     eDiTeD").unwrap(),
         cli::ElementSort::EditedDate(false)
     );
     assert_eq!(
-        cli::CLI::parse_sort_style("eDiTeD-asC"),
+        cli::CLI::parse_sort_style("eDiTeD-asC").unwrap(),
         cli::ElementSort::EditedDate(true)
     );
 
     //Test invalid params
-    assert_eq!(
-        cli::CLI::parse_sort_style("Edi   ted   "),
-        cli::ElementSort::Default
-    );
-    assert_eq!(cli::CLI::parse_sort_style("asd"), cli::ElementSort::Default);
-    assert_eq!(
-        cli::CLI::parse_sort_style("     "),
-        cli::ElementSort::Default
-    );
-    assert_eq!(cli::CLI::parse_sort_style("\n"), cli::ElementSort::Default);
-    assert_eq!(cli::CLI::parse_sort_style(""), cli::ElementSort::Default);
+    for o in ["Edi   ted   ", "asd", "     ", "\n", ""] {
+        assert!(matches!(
+            cli::CLI::parse_sort_style(o),
+            Err(SrpError::Validation(_))
+        ));
+    }
 }
 
 #[test]
 fn test_cli_parse_format() {
     //Test valid params
     assert_eq!(
-        cli::CLI::parse_format("default"),
-        String::from("output.txt")
+        cli::CLI::parse_format("default").unwrap(),
+        (Format::Default, String::from("output.txt"))
+    );
+    assert_eq!(
+        cli::CLI::parse_format("d").unwrap(),
+        (Format::Default, String::from("output.txt"))
+    );
+    assert_eq!(
+        cli::CLI::parse_format("html").unwrap(),
+        (Format::HTML, String::from("output.html"))
+    );
+    assert_eq!(
+        cli::CLI::parse_format("h").unwrap(),
+        (Format::HTML, String::from("output.html"))
+    );
+    assert_eq!(
+        cli::CLI::parse_format("json").unwrap(),
+        (Format::JSON, String::from("output.json"))
+    );
+    assert_eq!(
+        cli::CLI::parse_format("j").unwrap(),
+        (Format::JSON, String::from("output.json"))
     );
-    assert_eq!(get_safe!(FORMAT), crate::element::Format::Default);
-
-    assert_eq!(cli::CLI::parse_format("d"), String::from("output.txt"));
-    assert_eq!(get_safe!(FORMAT), crate::element::Format::Default);
-
-    assert_eq!(cli::CLI::parse_format("html"), String::from("output.html"));
-    assert_eq!(get_safe!(FORMAT), crate::element::Format::HTML);
-
-    assert_eq!(cli::CLI::parse_format("h"), String::from("output.html"));
-    assert_eq!(get_safe!(FORMAT), crate::element::Format::HTML);
-
-    assert_eq!(cli::CLI::parse_format("json"), String::from("output.json"));
-    assert_eq!(get_safe!(FORMAT), crate::element::Format::JSON);
-
-    assert_eq!(cli::CLI::parse_format("j"), String::from("output.json"));
-    assert_eq!(get_safe!(FORMAT), crate::element::Format::JSON);
 
     //Test edgecases
-    assert_eq!(cli::CLI::parse_format("J"), String::from("output.json"));
-    assert_eq!(get_safe!(FORMAT), crate::element::Format::JSON);
-
-    assert_eq!(cli::CLI::parse_format("HtMl"), String::from("output.html"));
-    assert_eq!(get_safe!(FORMAT), crate::element::Format::HTML);
+    assert_eq!(
+        cli::CLI::parse_format("J").unwrap(),
+        (Format::JSON, String::from("output.json"))
+    );
+    assert_eq!(
+        cli::CLI::parse_format("HtMl").unwrap(),
+        (Format::HTML, String::from("output.html"))
+    );
 
     //Test invalid params
-    assert_eq!(cli::CLI::parse_format("j"), String::from("output.json"));
-    assert_eq!(get_safe!(FORMAT), crate::element::Format::JSON);
-
-    //Reset for test_element
-    unsafe {
-        crate::element::FORMAT = crate::element::Format::Default;
-    }
+    assert!(matches!(
+        cli::CLI::parse_format("asd"),
+        Err(SrpError::Validation(_))
+    ));
 }
 
 #[test]
@@ -364,6 +656,42 @@ fn test_cli_parse_filter_style() {
         )
     );
 
+    //Metadata
+    assert_eq!(
+        cli::CLI::parse_filter_style(&String::from("op"), Some(&String::from("true")), None)
+            .unwrap(),
+        (1, cli::ElementFilter::Submitter(true))
+    );
+    assert_eq!(
+        cli::CLI::parse_filter_style(
+            &String::from("stickied"),
+            Some(&String::from("false")),
+            None
+        )
+        .unwrap(),
+        (1, cli::ElementFilter::Stickied(false))
+    );
+    assert_eq!(
+        cli::CLI::parse_filter_style(
+            &String::from("flair"),
+            Some(&String::from("==")),
+            Some(&String::from("Lokalne"))
+        )
+        .unwrap(),
+        (
+            2,
+            cli::ElementFilter::Flair(cli::ElementFilterOp::EqString(String::from("Lokalne")))
+        )
+    );
+    assert!(matches!(
+        cli::CLI::parse_filter_style(
+            &String::from("subreddit"),
+            Some(&String::from("<")),
+            Some(&String::from("rust"))
+        ),
+        Err(SrpError::Validation(_))
+    ));
+
     //Comments
     assert_eq!(
         cli::CLI::parse_filter_style(
@@ -558,15 +886,83 @@ fn test_cli_parse_filter_style() {
 
     //Test invalid params
 
-    assert_eq!(
-        cli::CLI::parse_filter_style(&String::from("asd"), None, None).unwrap(),
-        (0, cli::ElementFilter::Default)
-    );
+    assert!(matches!(
+        cli::CLI::parse_filter_style(&String::from("asd"), None, None),
+        Err(SrpError::Validation(_))
+    ));
 
-    assert_eq!(
-        cli::CLI::parse_filter_style(&String::from("E dited"), None, None).unwrap(),
-        (0, cli::ElementFilter::Default)
-    );
+    assert!(matches!(
+        cli::CLI::parse_filter_style(&String::from("E dited"), None, None),
+        Err(SrpError::Validation(_))
+    ));
+
+    //Invalid number
+    assert!(matches!(
+        cli::CLI::parse_filter_style(
+            &String::from("ups"),
+            Some(&String::from(">")),
+            Some(&String::from("ten"))
+        ),
+        Err(SrpError::Validation(_))
+    ));
+    assert!(matches!(
+        cli::CLI::parse_filter_style(
+            &String::from("comments"),
+            Some(&String::from("<=")),
+            Some(&String::from("1.5"))
+        ),
+        Err(SrpError::Validation(_))
+    ));
+
+    //Invalid operator
+    assert!(matches!(
+        cli::CLI::parse_filter_style(
+            &String::from("ups"),
+            Some(&String::from("=>")),
+            Some(&String::from("10"))
+        ),
+        Err(SrpError::Validation(_))
+    ));
+    assert!(matches!(
+        cli::CLI::parse_filter_style(
+            &String::from("comments"),
+            Some(&String::from("=")),
+            Some(&String::from("10"))
+        ),
+        Err(SrpError::Validation(_))
+    ));
+
+    //CLI::new doesn't ignore them either
+    let args = |o: &[&str]| o.iter().map(|o| st(o)).collect::<Vec<_>>();
+    assert!(matches!(
+        CLI::new(&args(&[
+            "srp",
+            "--filter",
+            "ups",
+            ">",
+            "ten",
+            "https://reddit.com/r/foo/comments/abc"
+        ])),
+        Err(SrpError::Validation(_))
+    ));
+    assert!(matches!(
+        CLI::new(&args(&[
+            "srp",
+            "--fliter",
+            "ups",
+            "https://reddit.com/r/foo/comments/abc"
+        ])),
+        Err(SrpError::Validation(_))
+    ));
+    assert!(matches!(
+        CLI::new(&args(&[
+            "srp",
+            "-v",
+            "loud",
+            "https://reddit.com/r/foo/comments/abc"
+        ])),
+        Err(SrpError::Validation(_))
+    ));
 }
 
 #[test]
@@ -604,23 +1000,23 @@ fn ups_sorted_elements_list(n: usize) -> Vec<Element> {
     let mut rng = rand::thread_rng();
 
     let mut out = Vec::new();
-    let mut ups = 0usize;
+    let mut ups = 0i64;
     for _ in 0..=n {
         ups += rng.gen_range(1..10);
         out.push(Element::new(
             st(""),
             st(""),
-            st(""),
+            Kind::Unknown,
             st(""),
             ups,
             vec![],
-            st(""),
+            0,
             st(""),
             st(""),
             st(""),
             false,
-            0,
-            1,
+            None,
+            Edited::Unknown,
         ));
     }
 
@@ -743,3 +1139,1895 @@ fn test_sort_elements_rng() {
         Ok(sorted_rev)
     );
 }
+
+#[test]
+fn test_sort_elements_top_level() {
+    let element = |id: &str, ups| {
+        let mut element = Element::default();
+        element.id = st(id);
+        element.ups = ups;
+        element
+    };
+    let cli = CLI {
+        sort_style: crate::cli::ElementSort::Upvotes(false),
+        ..Default::default()
+    };
+    //The post stays first, every comment after it is sorted
+    let elements = vec![
+        element("post", 0),
+        element("c1", 1),
+        element("c2", 3),
+        element("c3", 2),
+    ];
+    assert_eq!(
+        utils::sort_elements_(elements, &cli)
+            .unwrap()
+            .iter()
+            .map(|o| o.id.as_str())
+            .collect::<Vec<_>>(),
+        vec!["post", "c2", "c3", "c1"]
+    );
+}
+
+#[test]
+fn test_sessions_independent() {
+    let json_data =
+        serde_json::from_str::<serde_json::Value>(include_str!("element_test_data1.json")).unwrap();
+
+    let session1 = FetchSession::new(Format::Default, usize::MAX);
+    let session2 = FetchSession::new(Format::JSON, 5);
+    Element::init(json_data.clone(), &session1);
+    Element::init(json_data.clone(), &session2);
+    let count = session1.elements_count();
+    Element::init(json_data, &FetchSession::new(Format::Default, usize::MAX));
+
+    assert_eq!(session1.elements_count(), count);
+    assert_eq!(session2.elements_count(), 5);
+    assert_eq!(session1.num_comments(), session2.num_comments());
+}
+
+#[test]
+fn test_malformed_element() {
+    let json_data = serde_json::from_str::<serde_json::Value>(
+        r#"[
+        {"data": {"children": [{"kind": "t3", "data": {"name": "t3_abc", "id": "abc", "author": "a"}}]}},
+        {"data": {"children": [
+            {"kind": "t1", "data": {"name": "t1_c1", "id": "c1", "parent_id": "t3_abc", "author": "b",
+                "body": "text", "ups": "many", "replies": {"data": {"children": [
+                    {"kind": "t1", "data": {"name": "t1_c2", "id": "c2", "parent_id": "t1_c1", "author": "c"}}
+                ]}}}},
+            {"kind": "t1", "data": {"name": "t1_c3", "id": "c3", "parent_id": "t3_abc", "author": "d"}}
+        ]}}
+    ]"#,
+    )
+    .unwrap();
+    let session = FetchSession::new(Format::Default, usize::MAX);
+    let elements = Element::init(json_data, &session);
+
+    //Only the malformed field is lost, not the comment or it's replies
+    assert_eq!(
+        elements.iter().map(|o| o.id.as_str()).collect::<Vec<_>>(),
+        vec!["abc", "c1", "c3"]
+    );
+    assert_eq!(elements[1].author, st("b"));
+    assert_eq!(elements[1].data(), "text");
+    assert_eq!(elements[1].ups, 0);
+    assert_eq!(elements[1].children[0].id, st("c2"));
+    assert_eq!(
+        session
+            .malformed()
+            .into_iter()
+            .map(|o| o.0)
+            .collect::<Vec<_>>(),
+        vec![st("c1")]
+    );
+}
+
+#[test]
+fn test_element_metadata() {
+    let session = FetchSession::new(Format::Default, usize::MAX);
+    let thing = |kind: &str, data: json::JsonValue| {
+        Element::create(&json::object! {kind: kind, data: data}, &session).unwrap()
+    };
+    let post = thing(
+        "t3",
+        json::object! {
+            name: "t3_abc", id: "abc", author: "alice", title: "title", ups: 10, score: 10,
+            upvote_ratio: 0.75, link_flair_text: "News", author_flair_text: null,
+            distinguished: null, stickied: true, locked: true, subreddit: "rust",
+            edited: false, created: 1_700_000_000.0, total_awards_received: 2,
+        },
+    );
+    assert!(post.is_post());
+    assert_eq!(post.kind(), Kind::Post);
+    assert_eq!(post.edited, Edited::No);
+    assert_eq!(
+        post.created,
+        chrono::DateTime::from_timestamp(1_700_000_000, 0)
+    );
+    assert_eq!(post.meta.link_flair(), Some("News"));
+    assert_eq!(post.meta.author_flair(), None);
+    assert_eq!(
+        post.tags(),
+        vec![
+            st("r/rust"),
+            st("75% upvoted"),
+            st("flair: News"),
+            st("stickied"),
+            st("locked"),
+            st("2 awards")
+        ]
+    );
+
+    let comment = thing(
+        "t1",
+        json::object! {
+            name: "t1_def", id: "def", parent_id: "t3_abc", author: "bob", body: "text",
+            ups: -3, score: -3, depth: 2, is_submitter: true, distinguished: "moderator",
+            controversiality: 1, author_flair_text: "  ", edited: 1_700_000_100.0,
+            replies: "",
+        },
+    );
+    assert_eq!(comment.kind(), Kind::Comment);
+    assert_eq!(comment.parent_id, "abc");
+    assert_eq!(comment.ups, -3);
+    assert_eq!(
+        comment.edited,
+        Edited::At(chrono::DateTime::from_timestamp(1_700_000_100, 0).unwrap())
+    );
+    assert_eq!(
+        comment.tags(),
+        vec![st("OP"), st("moderator"), st("controversial")]
+    );
+    assert!(comment
+        .formatted(Format::Default)
+        .with_dates(&test_dates())
+        .to_string()
+        .starts_with("  2 -3 bob [OP] [moderator] [controversial] (edited just now): text"));
+    assert!(comment
+        .formatted(Format::HTML)
+        .to_string()
+        .contains("<span class=\"tag\">OP</span>"));
+
+    let json_data = json::parse(
+        comment
+            .formatted(Format::JSON)
+            .to_string()
+            .trim_end_matches(",\n"),
+    )
+    .unwrap();
+    assert_eq!(json_data["kind"], "t1");
+    assert_eq!(json_data["depth"], 2);
+    assert_eq!(json_data["edited"], "2023-11-14T22:15:00Z");
+    assert_eq!(json_data["is_submitter"], true);
+    assert_eq!(json_data["distinguished"], "moderator");
+    assert!(json_data["author_flair"].is_null());
+    assert_eq!(json_data["controversiality"], 1);
+
+    //Sort and filter
+    let mut quiet = comment.clone();
+    quiet.id = st("ghi");
+    quiet.meta = crate::model::Metadata::default();
+    let elements = vec![quiet.clone(), comment.clone()];
+    assert_eq!(
+        utils::sort_elements(elements.clone(), cli::ElementSort::Controversial).unwrap(),
+        vec![comment.clone(), quiet.clone()]
+    );
+    let filtered = |filter| {
+        utils::filter_elements(elements.clone(), filter, Vec::new())
+            .unwrap()
+            .0
+    };
+    assert_eq!(
+        filtered(cli::ElementFilter::Submitter(true)),
+        vec![comment.clone()]
+    );
+    assert_eq!(
+        filtered(cli::ElementFilter::Distinguished(false)),
+        vec![quiet.clone()]
+    );
+    assert_eq!(
+        filtered(cli::ElementFilter::Flair(cli::ElementFilterOp::EqString(
+            st("")
+        ))),
+        elements
+    );
+    assert_eq!(
+        filtered(cli::ElementFilter::Upvotes(cli::ElementFilterOp::Less(0))),
+        elements
+    );
+}
+
+#[test]
+fn test_element_state() {
+    use crate::model::State;
+
+    assert_eq!(State::classify("bob", "text", None), State::Live);
+    assert_eq!(
+        State::classify("[deleted]", "[deleted]", None),
+        State::Deleted
+    );
+    assert_eq!(
+        State::classify("[deleted]", "[removed]", None),
+        State::Removed
+    );
+    assert_eq!(State::classify("bob", "[removed]", None), State::Removed);
+    assert_eq!(
+        State::classify("[deleted]", "text", None),
+        State::AuthorDeleted
+    );
+    assert_eq!(
+        State::classify("bob", "", Some("moderator")),
+        State::Removed
+    );
+    assert_eq!(
+        State::classify("[deleted]", "", Some("deleted")),
+        State::Deleted
+    );
+    for state in [
+        State::Live,
+        State::Deleted,
+        State::Removed,
+        State::AuthorDeleted,
+    ] {
+        assert_eq!(State::parse(state.as_str()), Some(state));
+    }
+
+    //A removed post, it keeps it's title and replies
+    let session = FetchSession::new(Format::Default, usize::MAX);
+    let post = Element::create(
+        &json::object! {
+            kind: "t3",
+            data: {
+                name: "t3_abc", id: "abc", author: "[deleted]", title: "title",
+                selftext: "[removed]", removed_by_category: "moderator",
+                replies: {data: {children: [
+                    {kind: "t1", data: {name: "t1_a", id: "a", parent_id: "t3_abc", author: "", body: "[removed]"}},
+                    {kind: "t1", data: {name: "t1_b", id: "b", parent_id: "t3_abc", author: "[deleted]", body: "kept"}},
+                    {kind: "t1", data: {name: "t1_c", id: "c", parent_id: "t3_abc", author: "bob", body: "text"}},
+                ]}},
+            },
+        },
+        &session,
+    )
+    .unwrap();
+    assert_eq!(post.state, State::Removed);
+    assert!(!post.is_deleted());
+    let states = post
+        .children
+        .iter()
+        .map(|o| (o.id.as_str(), o.state, o.is_deleted()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        states,
+        vec![
+            ("a", State::Removed, true),
+            ("b", State::AuthorDeleted, true),
+            ("c", State::Live, false)
+        ]
+    );
+
+    //Comments without an author are still shown
+    let removed = &post.children[0];
+    assert_eq!(
+        removed.formatted(Format::Default).to_string(),
+        "0 0 [deleted] [removed by moderators]: [removed]\n"
+    );
+    assert!(removed
+        .formatted(Format::HTML)
+        .to_string()
+        .contains("<div class=\"element removed\">"));
+    let json_data = json::parse(
+        removed
+            .formatted(Format::JSON)
+            .to_string()
+            .trim_end_matches(",\n"),
+    )
+    .unwrap();
+    assert_eq!(json_data["state"], "removed");
+
+    let filtered = |filter| {
+        utils::filter_elements(post.children.clone(), filter, Vec::new())
+            .unwrap()
+            .0
+            .into_iter()
+            .map(|o| o.id)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(filtered(cli::ElementFilter::Deleted(false)), vec![st("c")]);
+    assert_eq!(
+        filtered(cli::ElementFilter::State(cli::ElementFilterOp::EqString(
+            st("author_deleted")
+        ))),
+        vec![st("b")]
+    );
+    assert_eq!(
+        cli::CLI::parse_filter_style(&st("state"), Some(&st("!=")), Some(&st("live"))).unwrap(),
+        (
+            2,
+            cli::ElementFilter::State(cli::ElementFilterOp::NotEqString(st("live")))
+        )
+    );
+
+    //The state is read back from json outputs
+    let output = crate::render(&[post], Format::JSON, &test_dates(), "");
+    let snapshot = crate::snapshot::Snapshot::from_output(&json::parse(&output).unwrap()).unwrap();
+    assert!(!snapshot.get("abc").unwrap().deleted);
+    assert!(snapshot.get("a").unwrap().deleted);
+    assert!(!snapshot.get("c").unwrap().deleted);
+}
+
+#[test]
+fn test_markdown() {
+    use crate::markdown::to_html;
+
+    //Raw html is escaped (the text is requested with raw_json=1)
+    assert_eq!(
+        to_html("<script>alert(1)</script> a & b"),
+        "<p>&lt;script&gt;alert(1)&lt;/script&gt; a &amp; b</p>\n"
+    );
+    assert_eq!(
+        to_html("<div onclick=\"x\">\n\ntext"),
+        "<p>&lt;div onclick=&quot;x&quot;&gt;\n</p>\n<p>text</p>\n"
+    );
+    //Entities are markdown's, they're decoded once
+    assert_eq!(
+        to_html("a <b> &amp;lt; &lt;"),
+        "<p>a &lt;b&gt; &amp;lt; &lt;</p>\n"
+    );
+    //Links
+    assert_eq!(
+        to_html("[a](https://example.com) [b](javascript:alert(1)) [c](/r/rust)"),
+        "<p><a href=\"https://example.com\">a</a> <a href=\"#\">b</a> \
+         <a href=\"https://www.reddit.com/r/rust\">c</a></p>\n"
+    );
+    assert_eq!(
+        to_html("![img](https://i.redd.it/a.png)"),
+        "<p><a href=\"https://i.redd.it/a.png\">img</a></p>\n"
+    );
+    //Quotes, lists, code and tables
+    assert_eq!(
+        to_html("> quote\n\n* one\n* two\n\n```\nlet a = 1 < 2;\n```\n\n`x < y`"),
+        "<blockquote>\n<p>quote</p>\n</blockquote>\n<ul>\n<li>one</li>\n<li>two</li>\n</ul>\n\
+         <pre><code>let a = 1 &lt; 2;\n</code></pre>\n<p><code>x &lt; y</code></p>\n"
+    );
+    assert_eq!(
+        to_html("a|b\n-|-\n1|~~2~~"),
+        "<table><thead><tr><th>a</th><th>b</th></tr></thead><tbody>\n\
+         <tr><td>1</td><td><del>2</del></td></tr>\n</tbody></table>\n"
+    );
+    //Spoilers and superscript
+    assert_eq!(
+        to_html("it was >!him!< all along >! not closed"),
+        "<p>it was <span class=\"spoiler\">him</span> all along &gt;! not closed</p>\n"
+    );
+    assert_eq!(
+        to_html("x^2 and ^(a few words) done"),
+        "<p>x<sup>2</sup> and <sup>a few words</sup> done</p>\n"
+    );
+    assert_eq!(
+        to_html("`>!a!< ^b`"),
+        "<p><code>&gt;!a!&lt; ^b</code></p>\n"
+    );
+    assert_eq!(
+        to_html("^(never closed"),
+        "<p><sup>never closed</sup></p>\n"
+    );
+
+    //Elements render their title and markdown, everything else is escaped
+    let session = FetchSession::new(Format::HTML, usize::MAX);
+    let post = Element::create(
+        &json::object! {
+            kind: "t3",
+            data: {
+                name: "t3_abc", id: "abc", author: "<b>bob</b>", title: "1 < 2",
+                url: "javascript:alert(1)", url_overridden_by_dest: "javascript:alert(1)",
+                selftext: "**bold** &lt;script&gt;",
+            },
+        },
+        &session,
+    )
+    .unwrap();
+    let html = post.formatted(Format::HTML).to_string();
+    assert!(html.contains("&lt;b&gt;bob&lt;/b&gt;</a>"));
+    assert!(html.contains("<a href=\"#\">javascript:alert(1)</a>"));
+    assert!(html.contains(
+        "<div class=\"data\"><h3>1 &lt; 2</h3><p><strong>bold</strong> &lt;script&gt;</p>\n</div>"
+    ));
+    assert!(!html.contains("<script>") && !html.contains("<b>"));
+}
+
+#[test]
+fn test_media() {
+    use crate::media::Media;
+
+    let session = FetchSession::new(Format::Default, usize::MAX);
+    let post = |data: json::JsonValue| {
+        Element::create(&json::object! {kind: "t3", data: data}, &session).unwrap()
+    };
+
+    //Galleries are in the order of gallery_data, unprocessed items fall back to i.redd.it
+    let gallery = post(json::object! {
+        name: "t3_a", id: "a", author: "bob", title: "pics",
+        gallery_data: {items: [
+            {media_id: "x1", caption: "first & best", outbound_url: "https://example.com"},
+            {media_id: "x2", caption: null},
+        ]},
+        media_metadata: {
+            x1: {status: "valid", e: "Image", m: "image/jpg",
+                s: {u: "https://preview.redd.it/x1.jpg?width=10&s=abc", x: 10, y: 20}},
+            x2: {status: "unprocessed", m: "image/png"},
+        },
+    });
+    let Some(Media::Gallery(items)) = &gallery.media else {
+        panic!("not a gallery: {:?}", gallery.media);
+    };
+    assert_eq!(items.len(), 2);
+    assert_eq!(
+        items[0].url,
+        "https://preview.redd.it/x1.jpg?width=10&s=abc"
+    );
+    assert_eq!(items[0].caption.as_deref(), Some("first & best"));
+    assert_eq!((items[0].width, items[0].height), (Some(10), Some(20)));
+    assert_eq!(items[1].url, "https://i.redd.it/x2.png");
+    assert_eq!(
+        gallery.formatted(Format::Default).to_string(),
+        "0 0 bob: pics\n"
+            .to_owned()
+            + "         [gallery, 2 items]\n"
+            + "         1. https://preview.redd.it/x1.jpg?width=10&s=abc - first & best (https://example.com)\n"
+            + "         2. https://i.redd.it/x2.png\n"
+    );
+    let html = gallery.formatted(Format::HTML).to_string();
+    assert!(html.contains(
+        "<figure><a href=\"https://preview.redd.it/x1.jpg?width=10&amp;s=abc\">\
+         <img src=\"https://preview.redd.it/x1.jpg?width=10&amp;s=abc\" alt=\"first &amp; best\" loading=\"lazy\"></a>\
+         <figcaption>first &amp; best <a href=\"https://example.com\">https://example.com</a></figcaption></figure>"
+    ));
+
+    //Poll options only have vote counts once the poll ended
+    let poll = post(json::object! {
+        name: "t3_b", id: "b", author: "bob", title: "vote",
+        poll_data: {
+            total_vote_count: 4, voting_end_timestamp: 1_675_000_000_000_u64,
+            options: [{text: "yes", id: "1", vote_count: 3}, {text: "no", id: "2", vote_count: 1}],
+        },
+    });
+    let text = poll
+        .formatted(Format::Default)
+        .with_dates(&test_dates())
+        .to_string();
+    assert!(text.contains("[poll, 4 votes, ended 2023-01-29 13:46]"));
+    assert!(text.contains("- yes: 3 votes (75%)"));
+    assert!(text.contains("- no: 1 vote (25%)"));
+    let json_data = json::parse(
+        poll.formatted(Format::JSON)
+            .to_string()
+            .trim_end_matches(",\n"),
+    )
+    .unwrap();
+    assert_eq!(json_data["media"]["type"], "poll");
+    assert_eq!(json_data["media"]["voting_end"], "2023-01-29T13:46:40Z");
+    assert_eq!(json_data["media"]["options"][0]["vote_count"], 3);
+
+    //A crosspost of a video, the video is on the original post
+    let crosspost = post(json::object! {
+        name: "t3_c", id: "c", author: "alice", title: "look", url: "/r/videos/comments/d/clip/",
+        is_video: false, media: null,
+        crosspost_parent_list: [{
+            name: "t3_d", id: "d", author: "bob", title: "clip", subreddit: "videos",
+            permalink: "/r/videos/comments/d/clip/", url: "https://v.redd.it/d",
+            selftext: "", ups: 10, num_comments: 1, created_utc: 1_675_000_000.0,
+            is_video: true,
+            media: {reddit_video: {
+                fallback_url: "https://v.redd.it/d/DASH_720.mp4", duration: 65,
+                width: 1280, height: 720, is_gif: false,
+            }},
+        }],
+    });
+    let Some(Media::Crosspost(original)) = &crosspost.media else {
+        panic!("not a crosspost: {:?}", crosspost.media);
+    };
+    assert_eq!((original.id.as_str(), original.ups), ("d", 10));
+    let text = crosspost.formatted(Format::Default).to_string();
+    assert!(text.contains(
+        "[crosspost of r/videos by bob, 10 ups, 1 comment] https://reddit.com/r/videos/comments/d/clip/"
+    ));
+    assert!(text.contains("[video, 1:05, 1280x720] https://v.redd.it/d/DASH_720.mp4"));
+    let html = crosspost.formatted(Format::HTML).to_string();
+    assert!(html.contains("<div class=\"media crosspost\">"));
+    assert!(html
+        .contains("<video src=\"https://v.redd.it/d/DASH_720.mp4\" width=\"1280\" height=\"720\""));
+    let json_data = json::parse(
+        crosspost
+            .formatted(Format::JSON)
+            .to_string()
+            .trim_end_matches(",\n"),
+    )
+    .unwrap();
+    assert_eq!(json_data["media"]["type"], "crosspost");
+    assert_eq!(json_data["media"]["media"]["type"], "video");
+    assert_eq!(json_data["media"]["media"]["duration"], 65);
+
+    //Text posts and comments have no media
+    assert_eq!(
+        post(json::object! {name: "t3_e", id: "e", selftext: "text"}).media,
+        None
+    );
+}
+
+#[test]
+fn test_date_format() {
+    use crate::date;
+
+    assert_eq!(Timezone::parse("UTC").unwrap(), Timezone::Utc);
+    assert_eq!(Timezone::parse("local").unwrap(), Timezone::Local);
+    assert_eq!(
+        Timezone::parse("+02:00").unwrap(),
+        Timezone::Fixed(chrono::FixedOffset::east_opt(7200).unwrap())
+    );
+    assert_eq!(
+        Timezone::parse("-0530").unwrap(),
+        Timezone::Fixed(chrono::FixedOffset::west_opt(19800).unwrap())
+    );
+    assert_eq!(
+        Timezone::parse("Europe/Warsaw").unwrap(),
+        Timezone::Named(chrono_tz::Europe::Warsaw)
+    );
+    assert!(Timezone::parse("Mars/Olympus").is_err());
+    assert!(Timezone::parse("+02:75").is_err());
+    //Split by characters, not bytes
+    assert!(matches!(
+        Timezone::parse("+a€"),
+        Err(SrpError::Validation(_))
+    ));
+    assert!(matches!(
+        Timezone::parse("+€:00"),
+        Err(SrpError::Validation(_))
+    ));
+    assert!(Timezone::parse("+-2").is_err());
+    assert!(DateFormat::validate_format("%d.%m.%Y").is_ok());
+    assert!(DateFormat::validate_format("%Y-%").is_err());
+
+    //f32 timestamps were off by minutes
+    let precise = date::from_timestamp(1_675_206_000.25).unwrap();
+    assert_eq!(precise.timestamp(), 1_675_206_000);
+    assert_eq!(precise.timestamp_subsec_millis(), 250);
+    assert_eq!(date::iso(&precise), "2023-01-31T23:00:00.250Z");
+
+    let mut dates = test_dates();
+    assert_eq!(dates.absolute(&precise), "2023-01-31 23:00");
+    dates.timezone = Timezone::parse("Europe/Warsaw").unwrap();
+    dates.format = st("%d.%m.%Y %H:%M");
+    assert_eq!(dates.absolute(&precise), "01.02.2023 00:00");
+    let ago = |secs: i64| {
+        dates.relative(&chrono::DateTime::from_timestamp(1_675_209_600 - secs, 0).unwrap())
+    };
+    assert_eq!(ago(-100), "just now");
+    assert_eq!(ago(59), "just now");
+    assert_eq!(ago(60 * 5), "5m ago");
+    assert_eq!(ago(3600 * 3), "3h ago");
+    assert_eq!(ago(86_400 * 2), "2d ago");
+    assert_eq!(ago(86_400 * 65), "2mo ago");
+    assert_eq!(ago(86_400 * 800), "2y ago");
+
+    //created_utc is preferred over created
+    let session = FetchSession::new(Format::Default, usize::MAX);
+    let comment = Element::create(
+        &json::object! {
+            kind: "t1",
+            data: {
+                name: "t1_def", id: "def", author: "bob", body: "text",
+                created: 1.0, created_utc: 1_675_200_000.0, edited: 1_675_206_000.0,
+            },
+        },
+        &session,
+    )
+    .unwrap();
+    assert_eq!(
+        comment.date_label(&test_dates()).unwrap(),
+        "2023-01-31 21:20, 2h ago, edited 1h ago"
+    );
+    let html = comment
+        .formatted(Format::HTML)
+        .with_dates(&test_dates())
+        .to_string();
+    assert!(
+        html.contains("<time datetime=\"2023-01-31T21:20:00Z\">2023-01-31 21:20</time> (2h ago)")
+    );
+    let json_data = json::parse(
+        comment
+            .formatted(Format::JSON)
+            .to_string()
+            .trim_end_matches(",\n"),
+    )
+    .unwrap();
+    assert_eq!(json_data["created"], "2023-01-31T21:20:00Z");
+    assert_eq!(json_data["edited"], "2023-01-31T23:00:00Z");
+
+    //Edited values of new and old json outputs
+    for (value, edited) in [
+        (json::JsonValue::from(false), Edited::No),
+        (json::JsonValue::from(true), Edited::Unknown),
+        (
+            json::JsonValue::from("2023-01-31T23:00:00Z"),
+            comment.edited,
+        ),
+        (json::JsonValue::from(1_675_206_000), comment.edited),
+        (json::JsonValue::from(usize::MAX), Edited::No),
+    ] {
+        assert_eq!(Edited::from_json(&value), edited);
+    }
+
+    let cli = CLI::new(&[
+        st("test-bin"),
+        st("--timezone"),
+        st("+01:00"),
+        st("--date-format"),
+        st("%d.%m %H:%M"),
+        st("https://reddit.com/r/foo/comments/abc/"),
+    ])
+    .unwrap();
+    assert_eq!(
+        cli.dates.timezone,
+        Timezone::Fixed(chrono::FixedOffset::east_opt(3600).unwrap())
+    );
+    assert_eq!(cli.dates.format, "%d.%m %H:%M");
+}
+
+#[test]
+fn test_render() {
+    assert_eq!(
+        crate::render(&[], Format::JSON, &test_dates(), ""),
+        st("{\"data\":[\n]}")
+    );
+    assert_eq!(
+        crate::render(
+            &[],
+            Format::Default,
+            &test_dates(),
+            "https://reddit.com/r/asd/"
+        ),
+        st("# {indent} {ups} {author} ({date}): {contnet}\n\nSource: https://reddit.com/r/asd/")
+    );
+
+    let json_data =
+        serde_json::from_str::<serde_json::Value>(include_str!("element_test_data1.json")).unwrap();
+    let session = FetchSession::new(Format::Default, usize::MAX);
+    let elements = crate::parse(json_data, &session).unwrap();
+    assert!(crate::render(&elements, Format::Default, &test_dates(), "")
+        .ends_with(include_str!("element_test_output1.txt")));
+}
+
+#[test]
+fn test_error_exit_codes() {
+    let errors = [
+        SrpError::Validation(st("")),
+        SrpError::Network(st("")),
+        SrpError::HttpStatus(404, st("")),
+        SrpError::JsonParse(st("")),
+        SrpError::Filesystem(st("")),
+    ];
+    let mut codes = errors.iter().map(SrpError::exit_code).collect::<Vec<_>>();
+    codes.sort_unstable();
+    codes.dedup();
+    assert_eq!(codes.len(), errors.len());
+    assert!(!codes.contains(&0));
+
+    assert_eq!(
+        SrpError::from(json::parse("{").unwrap_err()).exit_code(),
+        SrpError::JsonParse(st("")).exit_code()
+    );
+}
+
+fn element_with_id(id: &str, parent_id: &str) -> Element {
+    Element::new(
+        st("author"),
+        st("data"),
+        Kind::Comment,
+        st(""),
+        0,
+        vec![],
+        0,
+        st(""),
+        st(id),
+        st(parent_id),
+        false,
+        None,
+        Edited::No,
+    )
+}
+
+#[test]
+fn test_tree_builder() {
+    use crate::tree::TreeBuilder;
+
+    let mut tree = TreeBuilder::new("t3_post");
+    //Children arriving before their parents
+    assert_eq!(
+        tree.extend([
+            element_with_id("d", "c"),
+            element_with_id("post", ""),
+            element_with_id("c", "b"),
+        ]),
+        0
+    );
+    let mut parent = element_with_id("b", "post");
+    parent.children.push(element_with_id("c", "b"));
+    //c is a duplicate, e is new
+    parent.children.push(element_with_id("e", "b"));
+    assert_eq!(tree.extend([parent, element_with_id("f", "x")]), 1);
+    assert_eq!(tree.len(), 6);
+    assert!(tree.contains("e"));
+
+    let (elements, orphans) = tree.build();
+    assert_eq!(orphans, vec![st("f")]);
+    assert_eq!(
+        elements.iter().map(|o| o.id.as_str()).collect::<Vec<_>>(),
+        vec!["post", "b", "f"]
+    );
+    assert!(elements[0].children.is_empty());
+    assert_eq!(
+        elements[1]
+            .children
+            .iter()
+            .map(|o| o.id.as_str())
+            .collect::<Vec<_>>(),
+        vec!["c", "e"]
+    );
+    assert_eq!(
+        elements[1].children[0].children,
+        vec![element_with_id("d", "c")]
+    );
+
+    //The post id is taken from the post if link_id is unknown
+    let mut tree = TreeBuilder::new("");
+    tree.extend([element_with_id("post", ""), element_with_id("a", "post")]);
+    assert_eq!(
+        tree.build(),
+        (
+            vec![element_with_id("post", ""), element_with_id("a", "post")],
+            vec![]
+        )
+    );
+
+    //The first comment of a comment permalink's thread isn't an orphan
+    let mut tree = TreeBuilder::new("t3_post");
+    tree.extend_roots(vec![element_with_id("post", ""), element_with_id("p", "q")]);
+    tree.extend([element_with_id("a", "p"), element_with_id("b", "x")]);
+    let (mut elements, orphans) = tree.build();
+    assert_eq!(orphans, vec![st("b")]);
+    assert_eq!(elements[1].children, vec![element_with_id("a", "p")]);
+    assert!(crate::tree::focus(&mut elements, "a"));
+    assert!(elements[1].children[0].focused);
+    assert!(!crate::tree::focus(&mut elements, "z"));
+}
+
+test_wrap!(
+    url_origin,
+    utils::url_origin("https://reddit.com/r/foo/comments/abc/"),
+    st("https://reddit.com")
+);
+
+test_wrap!(
+    url_origin_no_path,
+    utils::url_origin("http://127.0.0.1:8080"),
+    st("http://127.0.0.1:8080")
+);
+
+#[test]
+fn test_scheduler_backoff() {
+    use crate::scheduler::RequestScheduler;
+    use std::time::Duration;
+
+    for retry in 0..4 {
+        let base = Duration::from_millis(500) * 2u32.pow(retry);
+        let backoff = RequestScheduler::backoff(retry);
+        assert!(backoff >= base && backoff <= base.mul_f64(1.5));
+    }
+    assert!(RequestScheduler::backoff(100) <= Duration::from_secs(90));
+    assert!(RequestScheduler::default().throttled().is_zero());
+}
+
+#[tokio::test]
+async fn test_run_bounded() {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    let running = Arc::new(AtomicUsize::new(0));
+    let max_running = Arc::new(AtomicUsize::new(0));
+    let tasks = (0..20).map(|i| {
+        let (running, max_running) = (Arc::clone(&running), Arc::clone(&max_running));
+        async move {
+            let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+            max_running.fetch_max(now, Ordering::SeqCst);
+            tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+            running.fetch_sub(1, Ordering::SeqCst);
+            i
+        }
+    });
+
+    let mut done = Vec::new();
+    crate::more::run_bounded(&CLI::default(), 3, tasks, |i| done.push(i)).await;
+
+    done.sort_unstable();
+    assert_eq!(done, (0..20).collect::<Vec<_>>());
+    assert!(max_running.load(Ordering::SeqCst) <= 3);
+}
+
+#[test]
+fn test_cli_client_config() {
+    let cli = CLI::new(&[
+        st("test-bin"),
+        st("--user-agent"),
+        st("srp-test"),
+        st("--timeout"),
+        st("0"),
+        st("--no-gzip"),
+        st("--proxy"),
+        st("socks5h://127.0.0.1:9050"),
+        st("-H"),
+        st("X-Foo: bar: baz"),
+        st("--cookie"),
+        st("over18=1"),
+        st("https://reddit.com/r/asd"),
+    ])
+    .unwrap();
+    assert_eq!(
+        cli.client,
+        ClientConfig {
+            user_agent: st("srp-test"),
+            timeout: None,
+            gzip: false,
+            proxy: Some(st("socks5h://127.0.0.1:9050")),
+            headers: vec![(st("X-Foo"), st("bar: baz"))],
+            cookies: vec![st("over18=1")],
+            ..Default::default()
+        }
+    );
+    assert!(cli.client.build(&cli.url).is_ok());
+
+    assert!(ClientConfig::parse_header(": foo").is_err());
+    assert!(ClientConfig::parse_header("foo").is_err());
+    assert!(ClientConfig {
+        proxy: Some(st("not a proxy")),
+        ..Default::default()
+    }
+    .build("")
+    .is_err());
+}
+
+#[test]
+fn test_offline_load() {
+    let dir = std::env::temp_dir().join("srp-test-offline");
+    let more_dir = dir.join("more_elements");
+    std::fs::create_dir_all(&more_dir).unwrap();
+    std::fs::write(
+        dir.join("raw.json"),
+        r#"[
+            {"data": {"children": [{"kind": "t3", "data": {
+                "name": "t3_abc", "id": "abc", "title": "title", "author": "a",
+                "permalink": "/r/foo/comments/abc/title/", "num_comments": 4
+            }}]}},
+            {"data": {"children": [
+                {"kind": "t1", "data": {"name": "t1_c1", "id": "c1", "parent_id": "t3_abc", "body": "1", "author": "b"}},
+                {"kind": "more", "data": {"count": 3, "children": ["c2", "c3"]}}
+            ]}}
+        ]"#,
+    )
+    .unwrap();
+    std::fs::write(
+        more_dir.join("morechildren_0.json"),
+        r#"{"json": {"errors": [], "data": {"things": [
+            {"kind": "t1", "data": {"name": "t1_c2", "id": "c2", "parent_id": "t3_abc", "body": "2", "author": "c"}},
+            {"kind": "t1", "data": {"name": "t1_c4", "id": "c4", "parent_id": "t1_c2", "body": "4", "author": "d"}}
+        ]}}}"#,
+    )
+    .unwrap();
+
+    let cli = CLI {
+        verbosity: cli::Verbosity::Low,
+        ..Default::default()
+    };
+    let session = FetchSession::new(Format::Default, usize::MAX);
+    let (elements, source) = crate::offline::load(&cli, &session, dir.to_str().unwrap()).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(
+        source,
+        st("https://www.reddit.com/r/foo/comments/abc/title/")
+    );
+    assert_eq!(
+        elements.iter().map(|o| o.id.as_str()).collect::<Vec<_>>(),
+        vec!["abc", "c1", "c2"]
+    );
+    assert_eq!(elements[2].children[0].id, st("c4"));
+    assert_eq!(session.num_comments(), 4);
+
+    assert!(matches!(
+        crate::offline::load(&cli, &session, "/nonexistent/srp/raw.json"),
+        Err(SrpError::Filesystem(_))
+    ));
+}
+
+#[test]
+fn test_checkpoint() {
+    use crate::checkpoint::Checkpoint;
+
+    assert_eq!(
+        Checkpoint::dir_for("https://www.reddit.com/r/foo/comments/abc/title/", None),
+        Checkpoint::default_dir().join("abc")
+    );
+    assert_eq!(
+        Checkpoint::dir_for("https://www.reddit.com/r/foo/comments/abc/title/c1/", None),
+        Checkpoint::default_dir().join("abc_c1")
+    );
+
+    let root = std::env::temp_dir().join("srp-test-checkpoint");
+    let dir = root.join("abc");
+    //The checkpoint of another post
+    let other = root.join("xyz");
+    std::fs::create_dir_all(&other).unwrap();
+
+    let url = "https://www.reddit.com/r/foo/comments/abc.json?raw_json=1";
+    let checkpoint = Checkpoint::create(&dir, url).unwrap();
+    assert!(dir.join("state.json").exists());
+    checkpoint
+        .save_raw(
+            &serde_json::from_str(
+                r#"[
+                {"data": {"children": [{"kind": "t3", "data": {"name": "t3_abc", "id": "abc", "author": "a"}}]}},
+                {"data": {"children": [
+                    {"kind": "t1", "data": {"name": "t1_c1", "id": "c1", "parent_id": "t3_abc", "author": "b"}},
+                    {"kind": "more", "data": {"count": 2, "children": ["c2", "c3"]}}
+                ]}}
+            ]"#,
+            )
+            .unwrap(),
+        )
+        .unwrap();
+    checkpoint
+        .save_batch(
+            &[st("c2")],
+            r#"{"json": {"errors": [], "data": {"things": [
+                {"kind": "t1", "data": {"name": "t1_c2", "id": "c2", "parent_id": "t1_c1", "author": "c"}}
+            ]}}}"#,
+        )
+        .unwrap();
+    //Written as soon as it arrives
+    assert!(dir.join("raw.json").exists());
+    assert!(dir.join("more_elements/morechildren_0.json").exists());
+    drop(checkpoint);
+
+    assert!(Checkpoint::open(
+        &dir,
+        "https://www.reddit.com/r/foo/comments/xyz.json?raw_json=1"
+    )
+    .unwrap()
+    .is_none());
+
+    let cli = CLI {
+        verbosity: cli::Verbosity::Low,
+        ..Default::default()
+    };
+    let session = FetchSession::new(Format::Default, usize::MAX)
+        .with_checkpoint(Checkpoint::open(&dir, url).unwrap().unwrap());
+    let elements = session
+        .checkpoint
+        .as_ref()
+        .unwrap()
+        .load(&cli, &session)
+        .unwrap();
+    assert_eq!(elements[1].children[0].id, st("c2"));
+    assert!(session.is_done("c2"));
+    assert!(!session.is_done("c3"));
+
+    //New batches continue after the saved ones
+    let checkpoint = session.checkpoint.as_ref().unwrap();
+    checkpoint.save_batch(&[st("c3")], "{}").unwrap();
+    assert!(dir.join("more_elements/morechildren_1.json").exists());
+
+    checkpoint.remove().unwrap();
+    assert!(!dir.exists());
+    assert!(other.exists());
+    assert!(Checkpoint::open(&dir, url).unwrap().is_none());
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn test_reconcile_report() {
+    use crate::reconcile::Report;
+
+    let json_data = serde_json::from_str::<serde_json::Value>(
+        r#"[
+        {"data": {"children": [{"kind": "t3", "data": {"name": "t3_abc", "id": "abc", "num_comments": 7}}]}},
+        {"data": {"children": [
+            {"kind": "t1", "data": {"name": "t1_c1", "id": "c1", "parent_id": "t3_abc", "body": "[removed]", "author": "[deleted]",
+                "replies": {"data": {"children": [
+                    {"kind": "more", "data": {"count": 0, "parent_id": "t1_c1", "children": []}}
+                ]}}}},
+            {"kind": "more", "data": {"count": 4, "children": ["c2", "c3", "c4", "c5"]}}
+        ]}}
+    ]"#,
+    )
+    .unwrap();
+    let session = FetchSession::new(Format::Default, usize::MAX);
+    let elements = crate::parse(json_data, &session).unwrap();
+    session.push_failed(vec![st("c2")], SrpError::Network(st("timeout")));
+
+    let cli = CLI::default();
+    let report = Report::new(&cli, &session, &elements);
+    assert_eq!(
+        report,
+        Report {
+            reported: 7,
+            fetched: 1,
+            never_returned: vec![st("c3"), st("c4"), st("c5")],
+            failed: vec![st("c2")],
+            continue_stubs: vec![st("c1")],
+            deleted: vec![st("c1")],
+            ..Default::default()
+        }
+    );
+    assert_eq!(report.difference(), 6);
+    assert_eq!(report.to_json()["failed"][0], "c2");
+
+    let cli = CLI {
+        req_more_elements: false,
+        ..Default::default()
+    };
+    let report = Report::new(&cli, &session, &elements);
+    assert_eq!(report.not_requested, vec![st("c3"), st("c4"), st("c5")]);
+    assert!(report.never_returned.is_empty());
+}
+
+//Serve the given (path, body) responses on a local port, returns the origin
+async fn mock_server(routes: Vec<(&'static str, String)>) -> String {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let origin = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+        loop {
+            let Ok((mut stream, _)) = listener.accept().await else {
+                return;
+            };
+            let routes = routes.clone();
+            tokio::spawn(async move {
+                let mut request = Vec::new();
+                let mut buf = [0u8; 1024];
+                while !request.ends_with(b"\r\n\r\n") {
+                    match stream.read(&mut buf).await {
+                        Ok(0) | Err(_) => return,
+                        Ok(n) => request.extend_from_slice(&buf[..n]),
+                    }
+                }
+                let request = String::from_utf8_lossy(&request);
+                let path = request.split(' ').nth(1).unwrap_or_default();
+                let response = match routes.iter().find(|o| path.starts_with(o.0)) {
+                    //"redirect <path>" bodies answer with a redirect
+                    Some((_, body)) if body.starts_with("redirect ") => format!(
+                        "HTTP/1.1 301 Moved Permanently\r\nLocation: {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                        &body["redirect ".len()..]
+                    ),
+                    Some((_, body)) => format!(
+                        "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                        body.len()
+                    ),
+                    None => String::from(
+                        "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                    ),
+                };
+                let _ = stream.write_all(response.as_bytes()).await;
+            });
+        }
+    });
+    origin
+}
+
+//Answers every request with the next of responses (raw http responses), the last one is repeated.
+//Returns the origin and the times the requests arrived at
+async fn mock_sequence(
+    responses: Vec<String>,
+) -> (
+    String,
+    std::sync::Arc<std::sync::Mutex<Vec<std::time::Instant>>>,
+) {
+    use std::sync::{Arc, Mutex};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let origin = format!("http://{}", listener.local_addr().unwrap());
+    let times = Arc::new(Mutex::new(Vec::new()));
+    let times_ = Arc::clone(&times);
+    tokio::spawn(async move {
+        loop {
+            let Ok((mut stream, _)) = listener.accept().await else {
+                return;
+            };
+            let mut request = Vec::new();
+            let mut buf = [0u8; 1024];
+            while !request.ends_with(b"\r\n\r\n") {
+                match stream.read(&mut buf).await {
+                    Ok(0) | Err(_) => break,
+                    Ok(n) => request.extend_from_slice(&buf[..n]),
+                }
+            }
+            let response = {
+                let mut times = times_.lock().unwrap();
+                times.push(std::time::Instant::now());
+                responses[(times.len() - 1).min(responses.len() - 1)].clone()
+            };
+            let _ = stream.write_all(response.as_bytes()).await;
+        }
+    });
+    (origin, times)
+}
+
+fn mock_response(status: &str, headers: &[&str], body: &str) -> String {
+    let headers = headers
+        .iter()
+        .map(|o| format!("{o}\r\n"))
+        .collect::<String>();
+    format!(
+        "HTTP/1.1 {status}\r\n{headers}Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )
+}
+
+#[tokio::test]
+async fn test_scheduler_retry_after() {
+    use crate::scheduler::RequestScheduler;
+    use std::time::Duration;
+
+    //429 with Retry-After, then a success
+    let (origin, times) = mock_sequence(vec![
+        mock_response("429 Too Many Requests", &["Retry-After: 1"], ""),
+        mock_response("200 OK", &[], "ok"),
+    ])
+    .await;
+    let scheduler = RequestScheduler::default();
+    let res = scheduler.request(&(origin + "/a")).await.unwrap();
+    assert_eq!(res.text().await.unwrap(), "ok");
+    let times = times.lock().unwrap().clone();
+    assert_eq!(times.len(), 2);
+    assert!(times[1] - times[0] >= Duration::from_secs(1));
+    assert!(scheduler.throttled() >= Duration::from_millis(900));
+
+    //Out of requests in this window, the next one waits for the reset
+    let (origin, times) = mock_sequence(vec![mock_response(
+        "200 OK",
+        &["x-ratelimit-remaining: 0", "x-ratelimit-reset: 1"],
+        "ok",
+    )])
+    .await;
+    let scheduler = RequestScheduler::default();
+    scheduler.request(&(origin.clone() + "/a")).await.unwrap();
+    assert!(scheduler.throttled().is_zero());
+    scheduler.request(&(origin + "/b")).await.unwrap();
+    let times = times.lock().unwrap().clone();
+    assert_eq!(times.len(), 2);
+    assert!(times[1] - times[0] >= Duration::from_secs(1));
+    assert!(scheduler.throttled() >= Duration::from_millis(900));
+}
+
+#[tokio::test]
+async fn test_scheduler_retry_status() {
+    use crate::scheduler::RequestScheduler;
+    use std::time::Duration;
+
+    //Server errors are retried after a backoff
+    let (origin, times) = mock_sequence(vec![
+        mock_response("503 Service Unavailable", &[], ""),
+        mock_response("200 OK", &[], "ok"),
+    ])
+    .await;
+    let scheduler = RequestScheduler::default();
+    assert!(scheduler.request(&(origin + "/a")).await.is_ok());
+    let times = times.lock().unwrap().clone();
+    assert_eq!(times.len(), 2);
+    assert!(times[1] - times[0] >= Duration::from_millis(500));
+    assert!(!scheduler.throttled().is_zero());
+
+    //Other errors aren't
+    let (origin, times) = mock_sequence(vec![mock_response("404 Not Found", &[], "")]).await;
+    let url = origin + "/a";
+    assert_eq!(
+        RequestScheduler::default().request(&url).await.unwrap_err(),
+        SrpError::HttpStatus(404, url)
+    );
+    assert_eq!(times.lock().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn test_fetch_more_continue_thread() {
+    use std::sync::Arc;
+
+    let post = r#"{"data": {"children": [{"kind": "t3", "data": {"name": "t3_abc", "id": "abc", "num_comments": 3}}]}}"#;
+    let origin = mock_server(vec![(
+        "/r/foo/comments/abc/c2.json?raw_json=1",
+        format!(
+            r#"[{post}, {{"data": {{"children": [
+                {{"kind": "t1", "data": {{"name": "t1_c2", "id": "c2", "parent_id": "t1_c1", "depth": 0, "replies": {{"data": {{"children": [
+                    {{"kind": "t1", "data": {{"name": "t1_c3", "id": "c3", "parent_id": "t1_c2", "depth": 1}}}}
+                ]}}}}}}}}
+            ]}}}}]"#
+        ),
+    )])
+    .await;
+
+    let json_data = serde_json::from_str::<serde_json::Value>(&format!(
+        r#"[{post}, {{"data": {{"children": [
+            {{"kind": "t1", "data": {{"name": "t1_c1", "id": "c1", "parent_id": "t3_abc", "depth": 0, "replies": {{"data": {{"children": [
+                {{"kind": "t1", "data": {{"name": "t1_c2", "id": "c2", "parent_id": "t1_c1", "depth": 1, "replies": {{"data": {{"children": [
+                    {{"kind": "more", "data": {{"count": 0, "parent_id": "t1_c2", "depth": 2, "children": []}}}}
+                ]}}}}}}}}
+            ]}}}}}}}}
+        ]}}}}]"#
+    ))
+    .unwrap();
+
+    let cli = CLI {
+        base_url: origin + "/r/foo/comments/abc/",
+        verbosity: cli::Verbosity::Low,
+        ..Default::default()
+    };
+    let session = Arc::new(FetchSession::new(Format::Default, usize::MAX));
+    let elements = crate::parse(json_data, &session).unwrap();
+    let elements = crate::fetch_more(&cli, &session, elements).await.unwrap();
+
+    assert_eq!(
+        elements.iter().map(|o| o.id.as_str()).collect::<Vec<_>>(),
+        vec!["abc", "c1"]
+    );
+    assert_eq!(elements[1].children[0].children[0].id, st("c3"));
+    //The continued page starts again at depth 0, the subtree keeps the depth of where it's attached
+    assert_eq!(elements[1].depth, 0);
+    assert_eq!(elements[1].children[0].depth, 1);
+    assert_eq!(elements[1].children[0].children[0].depth, 2);
+    assert!(session.is_continued("c2"));
+    assert_eq!(session.elements_count(), 5);
+    assert!(crate::reconcile::Report::new(&cli, &session, &elements)
+        .continue_stubs
+        .is_empty());
+}
+
+#[test]
+fn test_batch_parse_list() {
+    use crate::batch::parse_list;
+
+    assert_eq!(
+        parse_list(
+            "# posts to archive\n\
+             https://reddit.com/r/foo/comments/abc/title/ # the first one\n\
+             \n   \n\
+             \treddit.com/r/foo/comments/def\r\n\
+             #https://reddit.com/r/foo/comments/ghi"
+        ),
+        vec![
+            st("https://reddit.com/r/foo/comments/abc/title/"),
+            st("reddit.com/r/foo/comments/def")
+        ]
+    );
+    assert!(parse_list("# nothing here\n\n").is_empty());
+}
+
+test_wrap!(
+    batch_output_path,
+    crate::batch::output_path(
+        "out/post.html",
+        "https://reddit.com/r/foo/comments/abc/title/",
+        0
+    ),
+    st("out/post_abc.html")
+);
+
+test_wrap!(
+    batch_output_path_no_id,
+    crate::batch::output_path("output", "https://reddit.com/r/foo", 4),
+    st("output_5")
+);
+
+#[tokio::test]
+async fn test_batch_run() {
+    let origin = mock_server(vec![(
+        "/r/foo/comments/abc.json?raw_json=1",
+        st(r#"[{"data": {"children": [{"kind": "t3", "data": {"name": "t3_abc", "id": "abc", "title": "title", "author": "a", "num_comments": 0}}]}}, {"data": {"children": []}}]"#),
+    )])
+    .await;
+    let list = std::env::temp_dir().join("srp-test-batch.txt");
+    std::fs::write(
+        &list,
+        "https://www.reddit.com/r/foo/comments/abc\nhttps://example.com/foo\nhttps://www.reddit.com/r/foo/comments/xyz\n",
+    )
+    .unwrap();
+
+    let cli = CLI {
+        url: list.to_string_lossy().into_owned(),
+        host: Some(origin),
+        save_to_file: false,
+        verbosity: cli::Verbosity::Low,
+        batch: true,
+        ..Default::default()
+    };
+    let results = crate::batch::run(&cli).await.unwrap();
+    std::fs::remove_file(&list).unwrap();
+
+    assert_eq!(results.len(), 3);
+    assert_eq!(results[0].result, Ok(()));
+    assert!(matches!(results[1].result, Err(SrpError::Validation(_))));
+    assert!(matches!(
+        results[2].result,
+        Err(SrpError::HttpStatus(404, _))
+    ));
+    assert_eq!(results[0].output, st("-"));
+}
+
+#[test]
+fn test_listing_from_url() {
+    use crate::listing::{Listing, ListingSort};
+
+    assert_eq!(
+        Listing::from_url("https://www.reddit.com/r/rust/top/?t=week&foo=bar"),
+        Some(Listing {
+            subreddit: st("rust"),
+            sort: ListingSort::Top,
+            time: Some(st("week")),
+            limit: 25
+        })
+    );
+    assert_eq!(
+        Listing::from_url("reddit.com/r/rust/").map(|o| o.sort),
+        Some(ListingSort::Hot)
+    );
+    assert_eq!(
+        Listing::from_url("https://reddit.com/r/rust/comments/abc/title/"),
+        None
+    );
+    assert_eq!(Listing::from_url("https://reddit.com/r/"), None);
+    assert_eq!(Listing::from_url("https://reddit.com/r/rust/best"), None);
+
+    let listing = Listing::from_url("https://reddit.com/r/rust/new?t=day").unwrap();
+    //t is only used by top and controversial
+    assert_eq!(
+        listing.page_url("https://reddit.com", Some("t3_abc"), 10),
+        st("https://reddit.com/r/rust/new.json?raw_json=1&limit=10&after=t3_abc")
+    );
+
+    let cli = CLI::new(&[
+        st("test-bin"),
+        st("--listing"),
+        st("controversial"),
+        st("--time"),
+        st("year"),
+        st("--limit"),
+        st("150"),
+        st("https://reddit.com/r/rust"),
+    ])
+    .unwrap();
+    let listing = cli.listing.unwrap();
+    assert_eq!(
+        listing.page_url("https://reddit.com", None, 100),
+        st("https://reddit.com/r/rust/controversial.json?raw_json=1&limit=100&t=year")
+    );
+    assert_eq!(listing.limit, 150);
+}
+
+#[tokio::test]
+async fn test_listing_fetch_posts() {
+    use crate::{listing::Listing, scheduler::RequestScheduler};
+
+    let page = |ids: &[&str], after: &str| {
+        let children = ids
+            .iter()
+            .map(|o| {
+                format!(
+                    r#"{{"kind": "t3", "data": {{"permalink": "/r/rust/comments/{o}/title/"}}}}"#
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(",");
+        format!(r#"{{"data": {{"after": {after}, "children": [{children}]}}}}"#)
+    };
+    let origin = mock_server(vec![
+        (
+            "/r/rust/top.json?raw_json=1&limit=3&t=all&after=t3_b",
+            page(&["c"], "null"),
+        ),
+        (
+            "/r/rust/top.json?raw_json=1&limit=5&t=all",
+            page(&["a", "b"], r#""t3_b""#),
+        ),
+    ])
+    .await;
+
+    let mut listing = Listing::from_url("https://reddit.com/r/rust/top?t=all").unwrap();
+    listing.limit = 5;
+    let cli = CLI {
+        verbosity: cli::Verbosity::Low,
+        ..Default::default()
+    };
+    let posts = listing
+        .fetch_posts(&cli, &RequestScheduler::default(), &origin)
+        .await
+        .unwrap();
+    assert_eq!(
+        posts,
+        ["a", "b", "c"]
+            .iter()
+            .map(|o| format!("{origin}/r/rust/comments/{o}/title/"))
+            .collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn test_profile_from_url() {
+    use crate::profile::Profile;
+
+    assert_eq!(
+        Profile::from_url("https://www.reddit.com/user/bob/"),
+        Some(Profile {
+            user: st("bob"),
+            submitted: true,
+            comments: true,
+            limit: usize::MAX
+        })
+    );
+    let profile = Profile::from_url("reddit.com/u/bob/comments?sort=new").unwrap();
+    assert!(!profile.submitted && profile.comments);
+    assert_eq!(Profile::from_url("https://reddit.com/user/bob/saved"), None);
+    assert_eq!(Profile::from_url("https://reddit.com/r/rust"), None);
+
+    let cli = CLI::new(&[
+        st("test-bin"),
+        st("--limit"),
+        st("10"),
+        st("https://old.reddit.com/user/bob"),
+    ])
+    .unwrap();
+    assert_eq!(cli.profile.map(|o| o.limit), Some(10));
+    assert_eq!(cli.base_url, st("https://www.reddit.com/user/bob/"));
+    assert_eq!(cli.listing, None);
+}
+
+#[tokio::test]
+async fn test_profile_fetch() {
+    use crate::profile::Profile;
+
+    let comment = |id: &str, parent_id: &str| {
+        format!(
+            r#"{{"kind": "t1", "data": {{"name": "t1_{id}", "id": "{id}", "parent_id": "{parent_id}", "author": "bob",
+                "body": "{id}", "link_id": "t3_x", "link_title": "title", "link_author": "alice",
+                "link_permalink": "https://reddit.com/r/foo/comments/x/title/"}}}}"#
+        )
+    };
+    let origin = mock_server(vec![
+        (
+            "/user/bob/submitted.json",
+            st(r#"{"data": {"after": null, "children": [
+                {"kind": "t3", "data": {"name": "t3_y", "id": "y", "title": "mine", "author": "bob"}}
+            ]}}"#),
+        ),
+        (
+            "/user/bob/comments.json",
+            format!(
+                r#"{{"data": {{"after": null, "children": [{}, {}, {}, {}]}}}}"#,
+                comment("a", "t1_p"),
+                comment("b", "t3_x"),
+                comment("c", "t1_p"),
+                //Repeated by overlapping pages
+                comment("b", "t3_x")
+            ),
+        ),
+        (
+            "/api/info.json?raw_json=1&id=t1_p",
+            format!(
+                r#"{{"data": {{"children": [{}]}}}}"#,
+                comment("p", "t3_x").replace("\"bob\"", "\"carol\"")
+            ),
+        ),
+    ])
+    .await;
+
+    let cli = CLI {
+        verbosity: cli::Verbosity::Low,
+        ..Default::default()
+    };
+    let session = FetchSession::new(Format::Default, usize::MAX);
+    let elements = Profile::from_url("https://reddit.com/user/bob")
+        .unwrap()
+        .fetch(&cli, &session, &origin)
+        .await
+        .unwrap();
+
+    //Every comment is on post x, it's only added once
+    assert_eq!(
+        elements.iter().map(|o| o.id.as_str()).collect::<Vec<_>>(),
+        vec!["bob", "y", "x"]
+    );
+    let ids = |o: &Element| o.children.iter().map(|o| o.id.clone()).collect::<Vec<_>>();
+    assert_eq!(ids(&elements[2]), vec![st("p"), st("b")]);
+    //Comments a and c under their parent p
+    assert_eq!(elements[2].children[0].author, st("carol"));
+    assert_eq!(ids(&elements[2].children[0]), vec![st("a"), st("c")]);
+    //Comment b right under the post
+    assert!(elements[2].children[1].children.is_empty());
+
+    let output = crate::render(&elements, Format::Default, &test_dates(), "u/bob");
+    assert!(output.contains("1 0 carol: p") && output.contains("2 0 bob: a"));
+}
+
+#[test]
+fn test_reddit_url_context() {
+    use crate::url::RedditUrl;
+
+    let mut url = RedditUrl::parse(
+        "https://old.reddit.com/r/foo/comments/abc/_/DEF?context=8",
+        None,
+    )
+    .unwrap();
+    assert_eq!(url.comment(), Some("def"));
+    url.set_context(2);
+    assert_eq!(
+        url.urls().0,
+        st("https://www.reddit.com/r/foo/comments/abc/_/def.json?raw_json=1&context=2")
+    );
+    //Only comment permalinks have a context
+    let mut url = RedditUrl::parse("https://redd.it/abc", None).unwrap();
+    url.set_context(2);
+    assert_eq!(url.comment(), None);
+    assert_eq!(
+        url.urls().0,
+        st("https://www.reddit.com/comments/abc.json?raw_json=1")
+    );
+}
+
+#[tokio::test]
+async fn test_archive_comment_thread() {
+    use std::sync::Arc;
+
+    let comment = |id: &str, parent_id: &str, depth: usize, replies: &str| {
+        format!(
+            r#"{{"kind": "t1", "data": {{"name": "t1_{id}", "id": "{id}", "parent_id": "{parent_id}", "author": "bob",
+                "body": "{id}", "depth": {depth}, "replies": {replies}}}}}"#
+        )
+    };
+    let g = comment("g", "t1_def", 2, r#""""#);
+    let def = comment(
+        "def",
+        "t1_p",
+        1,
+        &format!(r#"{{"data": {{"children": [{g}]}}}}"#),
+    );
+    let p = comment(
+        "p",
+        "t1_q",
+        0,
+        &format!(r#"{{"data": {{"children": [{def}]}}}}"#),
+    );
+    let origin = mock_server(vec![(
+        "/r/foo/comments/abc/title/def.json?raw_json=1&context=1",
+        format!(
+            r#"[{{"data": {{"children": [{{"kind": "t3", "data": {{"name": "t3_abc", "id": "abc", "title": "title", "author": "a", "num_comments": 40}}}}]}}}},
+                {{"data": {{"children": [{p}]}}}}]"#
+        ),
+    )])
+    .await;
+
+    let output = std::env::temp_dir().join("srp-test-thread.txt");
+    let cli = CLI {
+        url: origin.clone() + "/r/foo/comments/abc/title/def/",
+        host: Some(origin),
+        save_path: output.to_string_lossy().into_owned(),
+        verbosity: cli::Verbosity::Low,
+        context: Some(1),
+        ..Default::default()
+    };
+    crate::archive_with(
+        &cli,
+        Arc::new(crate::scheduler::RequestScheduler::default()),
+    )
+    .await
+    .unwrap();
+    let content = std::fs::read_to_string(&output).unwrap();
+    std::fs::remove_file(&output).unwrap();
+
+    assert!(content.contains("0 0 bob: p"));
+    assert!(content.contains("1 0 bob [linked comment]: def"));
+    assert!(content.contains("2 0 bob: g"));
+}
+
+#[test]
+fn test_snapshot_changes() {
+    use crate::snapshot::{Change, Snapshot};
+
+    let comment = |id: &str, author: &str, data: &str, ups: i64| {
+        Element::new(
+            st(author),
+            st(data),
+            Kind::Comment,
+            st(""),
+            ups,
+            vec![],
+            1,
+            st(""),
+            st(id),
+            st("post"),
+            false,
+            None,
+            Edited::No,
+        )
+    };
+    let mut post = element_with_id("post", "");
+    post.children = vec![
+        comment("a", "bob", "first", 1),
+        comment("b", "bob", "second", 1),
+        comment("c", "carol", "third", 1),
+        comment("d", "dave", "fourth", 1),
+    ];
+    let old = Snapshot::new(&[post]);
+    assert_eq!(old.len(), 5);
+
+    let mut post = element_with_id("post", "");
+    let mut edited = comment("b", "bob", "second, edited", 1);
+    edited.edited = Edited::At(chrono::DateTime::from_timestamp(100, 0).unwrap());
+    post.children = vec![
+        comment("a", "bob", "first", 5),
+        edited,
+        comment("c", "[deleted]", "[deleted]", 1),
+        comment("e", "erin", "new", 1),
+    ];
+    let changes = old.changes(&Snapshot::new(&[post]));
+    assert_eq!(
+        changes,
+        vec![
+            Change::Score {
+                id: st("a"),
+                author: st("bob"),
+                old: 1,
+                new: 5
+            },
+            Change::Edited {
+                id: st("b"),
+                author: st("bob"),
+                old: st("second"),
+                new: st("second, edited")
+            },
+            Change::Deleted {
+                id: st("c"),
+                author: st("carol")
+            },
+            Change::Added {
+                id: st("e"),
+                parent_id: st("post"),
+                author: st("erin")
+            },
+            Change::Missing {
+                id: st("d"),
+                author: st("dave")
+            },
+        ]
+    );
+    assert!(!changes[0].is_activity() && changes[1].is_activity());
+    assert_eq!(changes[0].to_string(), "^ score a by bob: 1 -> 5");
+    assert!(old.changes(&old).is_empty());
+}
+
+#[test]
+fn test_cli_watch() {
+    use std::time::Duration;
+
+    assert_eq!(CLI::parse_duration("90"), Ok(Duration::from_secs(90)));
+    assert_eq!(CLI::parse_duration("10m"), Ok(Duration::from_secs(600)));
+    assert_eq!(CLI::parse_duration(" 2h"), Ok(Duration::from_secs(7200)));
+    assert_eq!(CLI::parse_duration("1d"), Ok(Duration::from_secs(86400)));
+    assert!(CLI::parse_duration("10 min").is_err());
+    assert!(CLI::parse_duration("m").is_err());
+    assert!(matches!(
+        CLI::parse_duration("0"),
+        Err(SrpError::Validation(_))
+    ));
+    assert!(matches!(
+        CLI::parse_duration("0m"),
+        Err(SrpError::Validation(_))
+    ));
+    assert!(matches!(
+        CLI::parse_duration(&format!("{}d", u64::MAX / 60)),
+        Err(SrpError::Validation(_))
+    ));
+
+    let cli = CLI::new(&[
+        st("test-bin"),
+        st("watch"),
+        st("--interval"),
+        st("5m"),
+        st("--deadline"),
+        st("1d"),
+        st("--quiet"),
+        st("2"),
+        st("https://reddit.com/r/foo/comments/abc/"),
+    ])
+    .unwrap();
+    assert_eq!(
+        cli.watch,
+        Some(crate::watch::Watch {
+            interval: Duration::from_secs(300),
+            deadline: Some(Duration::from_secs(86400)),
+            quiet_rounds: 2,
+            changelog: st("changelog.txt"),
+        })
+    );
+    assert_eq!(
+        cli.url,
+        st("https://www.reddit.com/r/foo/comments/abc.json?raw_json=1")
+    );
+    let cli = CLI::new(&[st("test-bin"), st("https://reddit.com/r/foo/comments/abc/")]).unwrap();
+    assert_eq!(cli.watch, None);
+}
+
+#[tokio::test]
+async fn test_watch_run() {
+    use crate::watch::Watch;
+
+    let origin = mock_server(vec![(
+        "/r/foo/comments/abc.json?raw_json=1",
+        st(r#"[{"data": {"children": [{"kind": "t3", "data": {"name": "t3_abc", "id": "abc", "title": "title", "author": "a", "num_comments": 1}}]}},
+            {"data": {"children": [{"kind": "t1", "data": {"name": "t1_c", "id": "c", "parent_id": "t3_abc", "author": "b", "body": "hi"}}]}}]"#),
+    )])
+    .await;
+    let output = std::env::temp_dir().join("srp-test-watch.txt");
+    let changelog = std::env::temp_dir().join("srp-test-watch-changelog.txt");
+    let _ = std::fs::remove_file(&changelog);
+    let watch = Watch {
+        interval: std::time::Duration::ZERO,
+        quiet_rounds: 2,
+        changelog: changelog.to_string_lossy().into_owned(),
+        ..Default::default()
+    };
+    let cli = CLI {
+        url: origin.clone() + "/r/foo/comments/abc.json?raw_json=1",
+        base_url: origin + "/r/foo/comments/abc/_/",
+        save_path: output.to_string_lossy().into_owned(),
+        verbosity: cli::Verbosity::Low,
+        watch: Some(watch.clone()),
+        ..Default::default()
+    };
+    crate::watch::run(&cli, &watch).await.unwrap();
+    let log = std::fs::read_to_string(&changelog).unwrap();
+    std::fs::remove_file(&changelog).unwrap();
+    std::fs::remove_file(&output).unwrap();
+
+    //The first round and two quiet ones
+    let lines = log.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 3);
+    assert!(lines[0].ends_with("round 1: 2 elements, first snapshot"));
+    assert!(lines[2].ends_with("round 3: 2 elements, 0 new, 0 edited, 0 deleted, 0 score changes"));
+
+    //The first round failing stops the watch
+    let cli = CLI {
+        url: cli.url.replace("abc", "xyz"),
+        ..cli
+    };
+    assert!(matches!(
+        crate::watch::run(&cli, &watch).await,
+        Err(SrpError::HttpStatus(404, _))
+    ));
+}
+
+#[test]
+fn test_text_diff() {
+    use crate::diff::{text_diff, DiffLine};
+
+    assert_eq!(
+        text_diff("a\nb\nc", "a\nc\nd"),
+        vec![
+            DiffLine::Same(st("a")),
+            DiffLine::Removed(st("b")),
+            DiffLine::Same(st("c")),
+            DiffLine::Added(st("d")),
+        ]
+    );
+    assert_eq!(text_diff("", "a"), vec![DiffLine::Added(st("a"))]);
+    assert!(text_diff("", "").is_empty());
+}
+
+#[test]
+fn test_diff() {
+    use crate::{diff, snapshot::Snapshot};
+
+    let render = |elements: &[Element]| crate::render(elements, Format::JSON, &test_dates(), "");
+    let comment = |id: &str, data: &str, ups: i64| {
+        Element::new(
+            st("bob"),
+            st(data),
+            Kind::Comment,
+            st(""),
+            ups,
+            vec![],
+            0,
+            st(""),
+            st(id),
+            st("abc"),
+            false,
+            None,
+            Edited::No,
+        )
+    };
+    let post = Element::new(
+        st("alice"),
+        st("title"),
+        Kind::Post,
+        st(""),
+        1,
+        vec![],
+        0,
+        st(""),
+        st("abc"),
+        st(""),
+        false,
+        None,
+        Edited::No,
+    );
+    let mut a = comment("a", "first\n<b>line</b>", 1);
+    a.children = vec![comment("b", "reply", 1), comment("c", "another", 1)];
+    let old = render(&[post.clone(), a]);
+
+    let mut a = comment("a", "first\n<i>line</i>", 3);
+    a.children = vec![comment("b", "[removed]", 1), comment("d", "new", 1)];
+    let new = render(&[post, a]);
+
+    //Every child is in the json output
+    let old = Snapshot::from_output(&json::parse(&old).unwrap()).unwrap();
+    assert_eq!(old.len(), 4);
+    assert_eq!(old.get("c").unwrap().parent_id, st("a"));
+    assert_eq!(old.get("a").unwrap().parent_id, st("abc"));
+    let new = Snapshot::from_output(&json::parse(&new).unwrap()).unwrap();
+    let changes = old.changes(&new);
+    assert_eq!(
+        changes
+            .iter()
+            .map(|o| (o.kind(), o.id()))
+            .collect::<Vec<_>>(),
+        vec![
+            ("edited", "a"),
+            ("score", "a"),
+            ("deleted", "b"),
+            ("added", "d"),
+            ("missing", "c")
+        ]
+    );
+
+    let text = diff::render(&old, &new, &changes, Format::Default, "old -> new");
+    assert!(text.starts_with("Diff: old -> new\n1 new, 1 edited, 2 deleted, 1 score changes\n"));
+    assert!(text.contains("~ edited a by bob\n      first\n    - <b>line</b>\n    + <i>line</i>\n"));
+    assert!(text.contains("+ new d by bob (reply to a)\n    + new\n"));
+
+    let html = diff::render(&old, &new, &changes, Format::HTML, "old -> new");
+    assert!(html.contains("<del>&lt;b&gt;line&lt;/b&gt;</del>"));
+    assert!(!html.contains("<b>line"));
+
+    let json_data = json::parse(&diff::render(&old, &new, &changes, Format::JSON, "")).unwrap();
+    assert_eq!(json_data["changes"][1]["new"], 3);
+    assert_eq!(json_data["changes"][3]["parent_id"], "a");
+
+    assert!(Snapshot::from_output(&json::parse("[]").unwrap()).is_err());
+
+    let cli = CLI::new(&[
+        st("test-bin"),
+        st("diff"),
+        st("-f"),
+        st("html"),
+        st("old.json"),
+        st("new.json"),
+    ])
+    .unwrap();
+    assert_eq!(
+        cli.diff,
+        Some(diff::Diff {
+            old: st("old.json")
+        })
+    );
+    assert_eq!((cli.url, cli.format), (st("new.json"), Format::HTML));
+}
@@ -0,0 +1,143 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::element::Element;
+
+//Assembles the element tree from elements arriving in any order.
+//Every element is indexed by it's id, children are found by parent_id,
+//so adding an element is O(1) and the tree is only put together in build.
+#[derive(Debug, Default)]
+pub struct TreeBuilder {
+    //Id of the post, elements replying to it are top level
+    post_id: String,
+    //Every element (without it's children), by id
+    nodes: HashMap<String, Element>,
+    //Ids of the children of every parent, in the order they were added
+    children: HashMap<String, Vec<String>>,
+    //Ids of every element in the order they were added
+    order: Vec<String>,
+    //Top level elements of the post's response, see extend_roots
+    roots: HashSet<String>,
+}
+
+impl TreeBuilder {
+    //link_id is the fullname of the post (t3_<id>), it can be empty
+    pub fn new(link_id: &str) -> Self {
+        Self {
+            post_id: link_id.strip_prefix("t3_").unwrap_or(link_id).to_owned(),
+            ..Default::default()
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn contains(&self, id: &str) -> bool {
+        self.nodes.contains_key(id)
+    }
+
+    //Add elements and all of their children
+    //Returns the number of elements skipped because they were already added
+    pub fn extend(&mut self, elements: impl IntoIterator<Item = Element>) -> usize {
+        elements.into_iter().map(|o| self.insert(o)).sum()
+    }
+
+    //Add the top level elements of the post's response, they stay at the top level
+    //even if their parent is missing (ex. the first comment of a comment permalink's thread)
+    pub fn extend_roots(&mut self, elements: Vec<Element>) -> usize {
+        self.roots.extend(elements.iter().map(|o| o.id.clone()));
+        self.extend(elements)
+    }
+
+    fn insert(&mut self, mut element: Element) -> usize {
+        let children = std::mem::take(&mut element.children);
+        let mut skipped = 0;
+        if self.nodes.contains_key(&element.id) {
+            skipped += 1;
+        } else {
+            if self.post_id.is_empty() && element.parent_id.is_empty() {
+                self.post_id = element.id.clone();
+            }
+            self.children
+                .entry(element.parent_id.clone())
+                .or_default()
+                .push(element.id.clone());
+            self.order.push(element.id.clone());
+            self.nodes.insert(element.id.clone(), element);
+        }
+        //Children of a duplicate can still be new (ex. a refetched subtree)
+        skipped + self.extend(children)
+    }
+
+    //Put the tree together
+    //Returns the top level elements and the orphans, elements whose parent was never added.
+    //Orphans are kept at the top level too, so they don't get lost.
+    pub fn build(mut self) -> (Vec<Element>, Vec<String>) {
+        let mut top = Vec::new();
+        let mut orphans = Vec::new();
+        for id in std::mem::take(&mut self.order) {
+            let Some(parent_id) = self.nodes.get(&id).map(|o| o.parent_id.clone()) else {
+                continue;
+            };
+            if parent_id.is_empty() || parent_id == self.post_id || self.roots.contains(&id) {
+                top.push(id);
+            } else if !self.nodes.contains_key(&parent_id) {
+                orphans.push(id.clone());
+                top.push(id);
+            }
+        }
+        let elements = top
+            .iter()
+            .filter_map(|o| self.take(o, None))
+            .collect::<Vec<_>>();
+        (elements, orphans)
+    }
+
+    //Remove the element and it's subtree from the index
+    //depth is set for children, subtrees of 'continue this thread' pages start again at 0
+    fn take(&mut self, id: &str, depth: Option<usize>) -> Option<Element> {
+        let mut element = self.nodes.remove(id)?;
+        if let Some(o) = depth {
+            element.depth = o;
+        }
+        //Replies to the post are kept next to it, not under it
+        if id == self.post_id {
+            return Some(element);
+        }
+        for child in self.children.remove(id).unwrap_or_default() {
+            if let Some(o) = self.take(&child, Some(element.depth + 1)) {
+                element.children.push(o);
+            }
+        }
+        Some(element)
+    }
+}
+
+//Mark the element with the id as focused (the comment of a comment permalink)
+//Returns false if it isn't in the tree
+pub fn focus(elements: &mut [Element], id: &str) -> bool {
+    let mut stack = elements.iter_mut().collect::<Vec<_>>();
+    while let Some(element) = stack.pop() {
+        if element.id == id {
+            element.focused = true;
+            return true;
+        }
+        stack.extend(&mut element.children);
+    }
+    false
+}
+
+//Ids of every element in the tree
+pub fn collect_ids(elements: &[Element]) -> HashSet<&str> {
+    let mut ids = HashSet::new();
+    let mut stack = elements.iter().collect::<Vec<_>>();
+    while let Some(element) = stack.pop() {
+        ids.insert(element.id.as_str());
+        stack.extend(&element.children);
+    }
+    ids
+}
@@ -0,0 +1,226 @@
+use crate::{cli::CLI, error::SrpError, scheduler::RequestScheduler};
+
+//Origin the urls of every reddit domain are normalised to
+pub const REDDIT_ORIGIN: &str = "https://www.reddit.com";
+
+//What a post url points to
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum UrlTarget {
+    //A post, or a comment of it (comment permalinks)
+    Post {
+        id: String,
+        //The title part of the url, if it had one
+        slug: Option<String>,
+        comment: Option<String>,
+        //Number of parent comments shown above the comment (?context=N)
+        context: Option<usize>,
+    },
+    //A share link (/r/<sub>/s/<token>), it only redirects to the post, see RedditUrl::resolve
+    Share(String),
+}
+
+//A normalised post url
+//Accepts reddit.com and all of it's subdomains (www, old, new, np, m, ...), redd.it/<id>,
+//comments with or without a slug, comment permalinks, galleries and share links
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RedditUrl {
+    //REDDIT_ORIGIN, or the --host
+    pub origin: String,
+    pub subreddit: Option<String>,
+    pub target: UrlTarget,
+}
+
+impl RedditUrl {
+    //Parse a post url, the origin gets replaced with host (see CLI::parse_host)
+    //Urls already pointing to host are accepted too
+    pub fn parse(url: &str, host: Option<&str>) -> Result<Self, SrpError> {
+        let host = host.map(CLI::parse_host).transpose()?;
+        let url = url.replace(['\'', ' ', '\n'], "");
+        let url = url.trim();
+        let invalid = || SrpError::Validation(format!("Invalid url: {url}"));
+
+        let parsed = if url.contains("://") {
+            reqwest::Url::parse(url)
+        } else {
+            reqwest::Url::parse(&("https://".to_owned() + url))
+        }
+        .map_err(|_| invalid())?;
+        let domain = parsed.host_str().ok_or_else(invalid)?.to_lowercase();
+        let with_port = parsed
+            .port()
+            .map_or_else(|| domain.clone(), |o| format!("{domain}:{o}"));
+
+        let is_host = host
+            .as_deref()
+            .is_some_and(|o| o.split_once("://").map_or(o, |o| o.1) == with_port);
+        let is_reddit = domain == "reddit.com" || domain.ends_with(".reddit.com");
+        if !is_host && !is_reddit && domain != "redd.it" {
+            return Err(invalid());
+        }
+        let origin = host.unwrap_or_else(|| REDDIT_ORIGIN.to_owned());
+
+        let mut segments = parsed
+            .path()
+            .split('/')
+            .filter(|o| !o.is_empty())
+            .map(|o| o.trim_end_matches(".json"))
+            .collect::<Vec<_>>();
+
+        //redd.it/<id>
+        if domain == "redd.it" {
+            return match segments[..] {
+                [id] => Self::post(origin, None, id, None, None).ok_or_else(invalid),
+                _ => Err(invalid()),
+            };
+        }
+
+        let subreddit = match segments[..] {
+            ["r", subreddit, ..] => Some(subreddit.to_owned()),
+            //Posts on a user's profile are in the u_<name> subreddit
+            ["user" | "u", user, "comments", ..] => Some(format!("u_{user}")),
+            _ => None,
+        };
+        if subreddit.is_some() {
+            segments.drain(..2);
+        }
+
+        match segments[..] {
+            ["comments", id, ref rest @ ..] if rest.len() <= 2 => {
+                let mut post = Self::post(origin, subreddit, id, rest.first(), rest.get(1))
+                    .ok_or_else(invalid)?;
+                let context = parsed
+                    .query_pairs()
+                    .find(|o| o.0 == "context")
+                    .and_then(|o| o.1.parse().ok());
+                if let Some(context) = context {
+                    post.set_context(context);
+                }
+                Ok(post)
+            }
+            ["gallery", id] if subreddit.is_none() => {
+                Self::post(origin, None, id, None, None).ok_or_else(invalid)
+            }
+            ["s", token] if subreddit.is_some() => Ok(Self {
+                origin,
+                subreddit,
+                target: UrlTarget::Share(token.to_owned()),
+            }),
+            _ => Err(invalid()),
+        }
+    }
+
+    //None if the ids aren't valid (base 36)
+    fn post(
+        origin: String,
+        subreddit: Option<String>,
+        id: &str,
+        slug: Option<&&str>,
+        comment: Option<&&str>,
+    ) -> Option<Self> {
+        let is_id = |o: &str| !o.is_empty() && o.chars().all(|o| o.is_ascii_alphanumeric());
+        if !is_id(id) || comment.is_some_and(|o| !is_id(o)) {
+            return None;
+        }
+        Some(Self {
+            origin,
+            subreddit,
+            target: UrlTarget::Post {
+                id: id.to_lowercase(),
+                slug: slug.map(|o| (*o).to_owned()),
+                comment: comment.map(|o| o.to_lowercase()),
+                context: None,
+            },
+        })
+    }
+
+    pub const fn is_share(&self) -> bool {
+        matches!(self.target, UrlTarget::Share(_))
+    }
+
+    //The post's id, None for share links
+    pub fn post_id(&self) -> Option<&str> {
+        match &self.target {
+            UrlTarget::Post { id, .. } => Some(id),
+            UrlTarget::Share(_) => None,
+        }
+    }
+
+    //Id of the comment a comment permalink points to
+    pub fn comment(&self) -> Option<&str> {
+        match &self.target {
+            UrlTarget::Post { comment, .. } => comment.as_deref(),
+            UrlTarget::Share(_) => None,
+        }
+    }
+
+    //Show n parent comments above the comment of a comment permalink, other urls are left as they are
+    pub fn set_context(&mut self, n: usize) {
+        if let UrlTarget::Post {
+            comment: Some(_),
+            context,
+            ..
+        } = &mut self.target
+        {
+            *context = Some(n);
+        }
+    }
+
+    //Origin and subreddit part of the url, ex. https://www.reddit.com/r/rust
+    fn prefix(&self) -> String {
+        match &self.subreddit {
+            Some(o) => format!("{}/r/{o}", self.origin),
+            None => self.origin.clone(),
+        }
+    }
+
+    //Returns the url of the post's json data, and the base url of the post
+    //(the url single comments are requested under, ex. <base_url><id>.json)
+    //Share links return the share link, resolve them first
+    pub fn urls(&self) -> (String, String) {
+        match &self.target {
+            UrlTarget::Post {
+                id,
+                slug,
+                comment,
+                context,
+            } => {
+                let post = format!("{}/comments/{id}", self.prefix());
+                let base_url = format!("{post}/{}/", slug.as_deref().unwrap_or("_"));
+                //raw_json=1, or reddit escapes <, > and & in the text
+                let url = match (comment, slug) {
+                    (Some(comment), _) => match context {
+                        Some(n) => format!("{base_url}{comment}.json?raw_json=1&context={n}"),
+                        None => format!("{base_url}{comment}.json?raw_json=1"),
+                    },
+                    (None, Some(slug)) => format!("{post}/{slug}.json?raw_json=1"),
+                    (None, None) => format!("{post}.json?raw_json=1"),
+                };
+                (url, base_url)
+            }
+            UrlTarget::Share(token) => {
+                let url = format!("{}/s/{token}", self.prefix());
+                (url.clone(), url + "/")
+            }
+        }
+    }
+
+    //Follow the redirect of a share link to the post, other urls are returned as they are
+    pub async fn resolve(
+        self,
+        scheduler: &RequestScheduler,
+        host: Option<&str>,
+    ) -> Result<Self, SrpError> {
+        if !self.is_share() {
+            return Ok(self);
+        }
+        let (url, _) = self.urls();
+        let res = scheduler.request(&url).await?;
+        let resolved = Self::parse(res.url().as_str(), host)?;
+        if resolved.is_share() {
+            return Err(SrpError::Validation(format!(
+                "The share link {url} didn't redirect to a post"
+            )));
+        }
+        Ok(resolved)
+    }
+}
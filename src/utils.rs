@@ -1,44 +1,22 @@
-extern crate tokio;
-
-use async_recursion::async_recursion;
 use console::{style, StyledObject};
-use json::JsonValue;
 use std::{
     io::Write,
+    sync::Mutex,
     time::{self, SystemTime},
 };
 
 use crate::{
     cli::{self, ElementFilter, ElementFilterOp, ElementSort, Verbosity, CLI},
-    element::{Element, Format, ELEMENTS_COUNT, FORMAT, NUM_COMMENTS},
+    element::Element,
+    error::SrpError,
     output_writer::OutputWriter,
+    session::FetchSession,
 };
 
 use rand::prelude::*;
 
 pub const TMP_DIR: &str = "tmp/";
 
-const USER_AGENT: &str = "Mozilla/5.0 (X11; Linux x86_64; rv:109.0) Gecko/20100101 Firefox/114.0";
-
-#[async_recursion]
-pub async fn request(url: String, retries: Option<usize>) -> Result<reqwest::Response, String> {
-    let retries = retries.unwrap_or(0);
-    let client = reqwest::Client::builder()
-        .user_agent(USER_AGENT)
-        .build()
-        .or(Err(String::from("Failed to build client.")))?;
-    match client.get(url.clone()).send().await {
-        Ok(o) => Ok(o),
-        Err(e) => {
-            if retries >= 3 {
-                Err(format!("Max retries exeeded, error: {e}"))
-            } else {
-                request(url, Some(retries + 1)).await
-            }
-        }
-    }
-}
-
 pub fn get_timestamp(get: bool) -> StyledObject<String> {
     if get {
         let now = time::SystemTime::now()
@@ -57,6 +35,82 @@ pub fn get_timestamp(get: bool) -> StyledObject<String> {
     }
 }
 
+//Print a progress line (overwriting the last one)
+pub fn print_progress(
+    verbosity: &Verbosity,
+    print_timestamps: bool,
+    idx: f64,
+    total: usize,
+    start: SystemTime,
+    last_line_length: &Mutex<usize>,
+) {
+    //calculate % of progress as a 64bit float
+    let precent = idx / (total as f64) * 100f64;
+
+    //get time passed since start of getting 'more' elements
+    let passed = SystemTime::now()
+        .duration_since(start)
+        .unwrap_or_default()
+        .as_secs_f64();
+
+    //Get estimated time
+    let eta = total as f64 / (idx / passed);
+
+    //Format the line to be printed
+    let mut line = format!(
+        "[{}::{}] {}{idx} / {total} {precent:.2}% runtime: {} ETA: {}",
+        style("CLI").bold(),
+        style("INFO").bold().green(),
+        get_timestamp(print_timestamps),
+        convert_time(passed),
+        convert_time(eta - passed)
+    );
+
+    let line_length = line.len();
+
+    let mut last_line_length = last_line_length
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner);
+    //Make sure there is no residual chars from last line
+    if line_length < *last_line_length {
+        line += &" ".repeat(*last_line_length - line_length);
+    }
+    *last_line_length = line_length;
+
+    //Print the line and flush stdout
+    //If you don't flush stdout not every line will be printed,
+    //Because print! doesn't flush as oppose to println!
+    if *verbosity == Verbosity::High || *verbosity == Verbosity::Moderate {
+        print!("\r{line}");
+        std::io::stdout().flush().unwrap_or_default();
+    }
+}
+
+//Escape text to be put in html
+pub fn escape_html(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out += "&amp;",
+            '<' => out += "&lt;",
+            '>' => out += "&gt;",
+            '"' => out += "&quot;",
+            '\'' => out += "&#39;",
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+//Get the scheme and host part of an url
+//https://reddit.com/r/foo/ -> https://reddit.com
+pub fn url_origin(url: &str) -> String {
+    let host_start = url.find("://").map_or(0, |i| i + 3);
+    url[host_start..]
+        .find('/')
+        .map_or_else(|| url.to_owned(), |i| url[..host_start + i].to_owned())
+}
+
 //Convert time in seconds to a more readable format
 // Xh Ymin Zs
 pub fn convert_time(t: f64) -> String {
@@ -148,28 +202,58 @@ pub fn filter_elements(
             }
         }
         ElementFilter::Edited(o) => {
-            elements.retain(|a| a.edited.eq(&usize::MAX) != o);
+            elements.retain(|a| a.edited.is_edited() == o);
+        }
+        //Posts have a flair of their own, comments only the flair of their author
+        ElementFilter::Flair(o) => retain_string(&mut elements, o, &req_elements, |a| {
+            a.meta
+                .link_flair()
+                .or_else(|| a.meta.author_flair())
+                .unwrap_or_default()
+        }),
+        ElementFilter::Subreddit(o) => {
+            retain_string(&mut elements, o, &req_elements, |a| &a.meta.subreddit);
+        }
+        ElementFilter::State(o) => {
+            retain_string(&mut elements, o, &req_elements, |a| a.state.as_str());
+        }
+        ElementFilter::Deleted(o) => {
+            elements.retain(|a| a.state.is_deleted() == o || req_elements.contains(&a.id));
+        }
+        ElementFilter::Submitter(o) => {
+            elements.retain(|a| a.meta.is_submitter == o || req_elements.contains(&a.id));
+        }
+        ElementFilter::Stickied(o) => {
+            elements.retain(|a| a.meta.stickied == o || req_elements.contains(&a.id));
+        }
+        ElementFilter::Distinguished(o) => {
+            elements
+                .retain(|a| a.meta.distinguished.is_some() == o || req_elements.contains(&a.id));
+        }
+        ElementFilter::Controversial(o) => {
+            elements.retain(|a| a.meta.is_controversial() == o || req_elements.contains(&a.id));
         }
         ElementFilter::Comments(o) => {
+            let len = |a: &Element| a.children.len() as i64;
             match o {
                 //Reamember to reverse number operators:
                 ElementFilterOp::Eq(o) => {
-                    elements.retain(|a| a.children.len() == o || req_elements.contains(&a.id));
+                    elements.retain(|a| len(a) == o || req_elements.contains(&a.id));
                 }
                 ElementFilterOp::NotEq(o) => {
-                    elements.retain(|a| a.children.len() != o || req_elements.contains(&a.id));
+                    elements.retain(|a| len(a) != o || req_elements.contains(&a.id));
                 }
                 ElementFilterOp::Less(o) => {
-                    elements.retain(|a| a.children.len() > o || req_elements.contains(&a.id));
+                    elements.retain(|a| len(a) > o || req_elements.contains(&a.id));
                 }
                 ElementFilterOp::LessEq(o) => {
-                    elements.retain(|a| a.children.len() >= o || req_elements.contains(&a.id));
+                    elements.retain(|a| len(a) >= o || req_elements.contains(&a.id));
                 }
                 ElementFilterOp::Grater(o) => {
-                    elements.retain(|a| a.children.len() < o || req_elements.contains(&a.id));
+                    elements.retain(|a| len(a) < o || req_elements.contains(&a.id));
                 }
                 ElementFilterOp::GraterEq(o) => {
-                    elements.retain(|a| a.children.len() <= o || req_elements.contains(&a.id));
+                    elements.retain(|a| len(a) <= o || req_elements.contains(&a.id));
                 }
                 _ => {} //invalid for this type(ElementFilter::Comments), only number operators apply
             }
@@ -183,11 +267,30 @@ pub fn filter_elements(
     Some((elements, req_elements))
 }
 
+//Keep the elements whose value matches the string operator, and the required ones
+fn retain_string(
+    elements: &mut Vec<Element>,
+    op: ElementFilterOp,
+    req_elements: &[String],
+    value: impl Fn(&Element) -> &str,
+) {
+    match op {
+        ElementFilterOp::EqString(o) => {
+            elements.retain(|a| value(a) == o || req_elements.contains(&a.id));
+        }
+        ElementFilterOp::NotEqString(o) => {
+            elements.retain(|a| value(a) != o || req_elements.contains(&a.id));
+        }
+        _ => {} //only string operations apply
+    }
+}
+
 pub fn write_to_output(
     cli: &cli::CLI,
-    elements: &Vec<Element>,
+    session: &FetchSession,
+    elements: &[Element],
     start: SystemTime,
-) -> Result<(), String> {
+) -> Result<(), SrpError> {
     //Set the default output to stdout
     let mut output: Box<dyn Write> = Box::new(std::io::stdout());
 
@@ -201,45 +304,19 @@ pub fn write_to_output(
             .open(cli.save_path.clone())
         {
             Ok(o) => Box::new(o),
-            Err(e) => return Err(format!("Failed to open file with error: {e}")),
+            Err(e) => {
+                return Err(SrpError::Filesystem(format!(
+                    "Failed to open {} with error: {e}",
+                    cli.save_path
+                )))
+            }
         };
         cli.print_info_nn(format!("Writing to {}: ", cli.save_path));
     }
     let mut ow = OutputWriter::new();
     ow.set_output(output);
 
-    //Write begining of the file:
-    match get_safe!(FORMAT) {
-        Format::Default => {
-            ow.content += &format!(
-                "# {{indent}} {{ups}} {{author}}: {{contnet}}\n\nSource: {}",
-                cli.base_url
-            );
-        }
-        Format::HTML => {
-            ow.content += &include_str!("html_file.html").replace("{title}", &cli.base_url);
-        }
-        Format::JSON => ow.content += "{\"data\":[",
-    }
-
-    //Write every element to the output.
-    //For formatting see element.rs:
-    //                   impl std::fmt::Debug for Element
-    for elem in elements {
-        ow.content += &format!("{elem}");
-    }
-
-    //Write the end:
-    match get_safe!(FORMAT) {
-        Format::Default => {}
-        Format::HTML => ow.content += "\t</div>\n</body>\n</html>",
-        Format::JSON => {
-            if let Some(r) = ow.content.clone().strip_suffix(",\n") {
-                ow.content = r.to_owned();
-            }
-            ow.content += "\n]}";
-        }
-    }
+    ow.content = crate::render(elements, session.format, &cli.dates, &cli.base_url);
 
     match ow.write() {
         Ok(_) => {
@@ -251,51 +328,57 @@ pub fn write_to_output(
                 }
             }
         }
-        Err(e) => return Err(format!("Failed to write to output with error:\n{e}")),
+        Err(e) => {
+            return Err(SrpError::Filesystem(format!(
+                "Failed to write to output with error:\n{e}"
+            )))
+        }
     }
 
     //Print last bit of debug data
-    //TODO: fix descrepency!!!
 
+    let elements_count = session.elements_count();
     cli.print_infol(format!(
         "Successfully got {} element{}, in {}",
-        get_safe!(ELEMENTS_COUNT),
-        if get_safe!(ELEMENTS_COUNT) == 1 {
-            ""
-        } else {
-            "s"
-        },
+        elements_count,
+        if elements_count == 1 { "" } else { "s" },
         convert_time(start.elapsed().unwrap().as_secs_f64())
     ));
 
-    let diff = (get_safe!(NUM_COMMENTS) as i32 - get_safe!(ELEMENTS_COUNT) as i32).abs();
-    if diff != 0 {
-        cli.print_info(format!("Difference: {diff}"));
+    let throttled = session.scheduler.throttled();
+    if !throttled.is_zero() {
+        cli.print_infom(format!(
+            "Throttled by rate limits for {}",
+            convert_time(throttled.as_secs_f64())
+        ));
     }
     Ok(())
 }
 
-pub fn sort_elements_(mut elements: Vec<Element>, cli: &CLI) -> Vec<Element> {
+pub fn sort_elements_(mut elements: Vec<Element>, cli: &CLI) -> Result<Vec<Element>, SrpError> {
     if elements.len() > 1 {
         //Filter elements.
         elements = match filter_elements(elements, cli.filter.clone(), vec![]) {
             Some(o) => o.0,
-            None => cli.print_err("Error, no elements, after filtering."),
+            None => {
+                return Err(SrpError::Validation(String::from(
+                    "Error, no elements, after filtering.",
+                )))
+            }
         };
         //Sort elements.
         if elements.len() > 2 {
-            let mut elements_cp = Vec::from([elements.get(0).map_or_else(
-                || cli.print_err("Error, invalid elements!"),
-                std::clone::Clone::clone,
-            )]);
+            let mut elements_cp = Vec::from([elements
+                .first()
+                .ok_or_else(|| SrpError::Validation(String::from("Error, invalid elements!")))?
+                .clone()]);
             elements_cp.append(
-                &mut sort_elements(elements[1..elements.len() - 1].to_vec(), cli.sort_style)
-                    .unwrap_or_default(),
+                &mut sort_elements(elements[1..].to_vec(), cli.sort_style).unwrap_or_default(),
             );
             elements = elements_cp;
         }
     }
-    elements
+    Ok(elements)
 }
 
 pub fn sort_elements(
@@ -312,18 +395,21 @@ pub fn sort_elements(
             let mut rng = rand::thread_rng();
             elements.shuffle(&mut rng);
         }
-        ElementSort::Upvotes(false) => elements.sort_by(|a, b| b.ups.cmp(&a.ups)),
-        ElementSort::Upvotes(true) => elements.sort_by(|a, b| a.ups.cmp(&b.ups)),
+        ElementSort::Upvotes(false) => elements.sort_by_key(|e| std::cmp::Reverse(e.ups)),
+        ElementSort::Upvotes(true) => elements.sort_by_key(|e| e.ups),
         ElementSort::Comments(false) => {
-            elements.sort_by(|a, b| b.children.len().cmp(&a.children.len()));
+            elements.sort_by_key(|e| std::cmp::Reverse(e.children.len()));
         }
-        ElementSort::Comments(true) => {
-            elements.sort_by(|a, b| a.children.len().cmp(&b.children.len()));
+        ElementSort::Comments(true) => elements.sort_by_key(|e| e.children.len()),
+        ElementSort::Date(false) => elements.sort_by_key(|e| std::cmp::Reverse(e.created)),
+        ElementSort::Date(true) => elements.sort_by_key(|e| e.created),
+        ElementSort::EditedDate(false) => elements.sort_by_key(|e| std::cmp::Reverse(e.edited)),
+        ElementSort::EditedDate(true) => elements.sort_by_key(|e| e.edited),
+        ElementSort::Awards(false) => elements.sort_by_key(|e| std::cmp::Reverse(e.meta.awards)),
+        ElementSort::Awards(true) => elements.sort_by_key(|e| e.meta.awards),
+        ElementSort::Controversial => {
+            elements.sort_by_key(|e| std::cmp::Reverse(e.meta.controversiality));
         }
-        ElementSort::Date(false) => elements.sort_by(|a, b| b.created.cmp(&a.created)),
-        ElementSort::Date(true) => elements.sort_by(|a, b| a.created.cmp(&b.created)),
-        ElementSort::EditedDate(false) => elements.sort_by(|a, b| b.edited.cmp(&a.edited)),
-        ElementSort::EditedDate(true) => elements.sort_by(|a, b| a.edited.cmp(&b.edited)),
     }
 
     for element in &mut elements {
@@ -336,81 +422,8 @@ pub fn sort_elements(
     Ok(elements)
 }
 
-pub fn delete_tmp() -> Result<(), String> {
-    std::fs::remove_dir_all(TMP_DIR).map_err(|_| String::from("Failed to delete temp files!"))?;
+pub fn delete_tmp() -> Result<(), SrpError> {
+    std::fs::remove_dir_all(TMP_DIR)
+        .map_err(|e| SrpError::Filesystem(format!("Failed to delete temp files: {e}")))?;
     Ok(())
 }
-
-pub async fn init() -> (CLI, JsonValue) {
-    let args: Vec<String> = std::env::args().collect();
-    let start = time::SystemTime::now();
-    let cli = crate::cli::CLI::new(&args);
-
-    cli.print_info("Initialising CLI: success");
-    cli.print_infom(format!("Requesting content from {}:", cli.url));
-    let Ok(res) = request(cli.url.clone(), None).await else{
-        CLI::print_err_no_timestamp("Fail");
-    };
-
-    let data = (res.text().await).map_or_else(
-        |_| {
-            CLI::print_err_no_timestamp("Fail");
-        },
-        |o| {
-            cli.print_infom(format!(
-                "Success in {}",
-                convert_time(start.elapsed().unwrap().as_secs_f64())
-            ));
-            o
-        },
-    );
-
-    let json_data = json::parse(&data).map_or_else(
-        |e| {
-            println!("{data}");
-            CLI::print_err_no_timestamp(format!("Parsing to JSON error: {e}"));
-        },
-        |o| {
-            cli.print_info("Parsing to JSON: success");
-            o
-        },
-    );
-
-    if cli.save_tmp_files {
-        let tmp_dir = std::path::Path::new(TMP_DIR);
-        if !tmp_dir.exists() {
-            std::fs::create_dir(tmp_dir).unwrap();
-        }
-
-        match std::fs::write(TMP_DIR.to_owned() + "raw.json", json_data.pretty(1)) {
-            Ok(_) => {
-                cli.print_info("Writing to JSON file: success");
-            }
-            Err(e) => {
-                CLI::print_err_no_timestamp(format!("Writing to JSON file: fail.\nError: {e}"))
-            }
-        }
-    }
-
-    if cli.save_tmp_files {
-        let tmp_dir = std::path::Path::new(TMP_DIR);
-        if !tmp_dir.exists() {
-            std::fs::create_dir(tmp_dir).unwrap();
-        }
-
-        match std::fs::write(TMP_DIR.to_owned() + "raw.json", json_data.pretty(1)) {
-            Ok(_) => {
-                cli.print_info("Writing to JSON file: success");
-            }
-            Err(e) => {
-                CLI::print_err_no_timestamp(format!("Writing to JSON file: fail.\nError: {e}"))
-            }
-        }
-    }
-    cli.print_info(format!(
-        "Initialising success, in {}",
-        convert_time(start.elapsed().unwrap().as_secs_f64())
-    ));
-
-    (cli, json_data)
-}
@@ -0,0 +1,144 @@
+use std::{
+    io::Write,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
+};
+
+use console::style;
+
+use crate::{
+    cli::CLI,
+    error::SrpError,
+    scheduler::RequestScheduler,
+    session::FetchSession,
+    snapshot::{self, Change, Snapshot},
+};
+
+//Settings of `srp watch`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Watch {
+    //Time between the start of two rounds
+    pub interval: Duration,
+    //Stop once this much time passed since the first round
+    pub deadline: Option<Duration>,
+    //Stop after this many rounds in a row without activity (see Change::is_activity)
+    pub quiet_rounds: usize,
+    //The changes of every round are appended to this file
+    pub changelog: String,
+}
+
+impl Default for Watch {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(10 * 60),
+            deadline: None,
+            quiet_rounds: 6,
+            changelog: String::from("changelog.txt"),
+        }
+    }
+}
+
+//Re-archive the post every interval, and log what changed since the last round,
+//until the thread goes quiet or the deadline passes.
+//A failed round is logged and skipped, only a failed first round stops the watch.
+pub async fn run(cli: &CLI, watch: &Watch) -> Result<(), SrpError> {
+    let started = Instant::now();
+    let scheduler = Arc::new(RequestScheduler::new(cli.client.build(&cli.url)?));
+    let resolved_cli = crate::resolve_url(cli, &scheduler).await?;
+    let cli = resolved_cli.as_ref().unwrap_or(cli);
+
+    let mut previous: Option<Snapshot> = None;
+    let mut quiet = 0;
+    for round in 1.. {
+        let round_start = Instant::now();
+        cli.print_infom(format!(
+            "{} round {round}: {}",
+            style("Watch").bold(),
+            cli.url
+        ));
+        match archive_round(cli, &scheduler).await {
+            Ok(snapshot) => {
+                let changes = previous.as_ref().map(|o| o.changes(&snapshot));
+                log_round(&watch.changelog, round, &snapshot, changes.as_deref())?;
+                match &changes {
+                    Some(changes) if !changes.iter().any(Change::is_activity) => quiet += 1,
+                    _ => quiet = 0,
+                }
+                previous = Some(snapshot);
+            }
+            Err(e) if previous.is_none() => return Err(e),
+            Err(e) => {
+                cli.print_warning(format!("Round {round} failed: {e}"));
+                append(
+                    &watch.changelog,
+                    &format!("{} round {round}: failed: {e}\n", now()),
+                )?;
+            }
+        }
+
+        if quiet >= watch.quiet_rounds {
+            cli.print_infom(format!(
+                "No activity in {quiet} round{}, stopping",
+                if quiet == 1 { "" } else { "s" }
+            ));
+            break;
+        }
+        let next = round_start + watch.interval;
+        if watch
+            .deadline
+            .is_some_and(|o| next.duration_since(started) >= o)
+        {
+            cli.print_infom("The deadline passed, stopping");
+            break;
+        }
+        tokio::time::sleep_until(next.into()).await;
+    }
+    Ok(())
+}
+
+//Archive the post once, returns the snapshot of it's elements
+async fn archive_round(cli: &CLI, scheduler: &Arc<RequestScheduler>) -> Result<Snapshot, SrpError> {
+    let start = SystemTime::now();
+    let session = Arc::new(
+        FetchSession::new(cli.format, cli.max_comments).with_scheduler(Arc::clone(scheduler)),
+    );
+    let json_data = crate::fetch(cli, &session).await?;
+    let elements = crate::parse(json_data, &session)?;
+    let elements = crate::fetch_more(cli, &session, elements).await?;
+    let snapshot = Snapshot::new(&elements);
+    crate::finish(cli, &session, elements, start)?;
+    Ok(snapshot)
+}
+
+//Append the changes of a round to the change log, changes is None for the first round
+fn log_round(
+    path: &str,
+    round: usize,
+    snapshot: &Snapshot,
+    changes: Option<&[Change]>,
+) -> Result<(), SrpError> {
+    let mut entry = format!("{} round {round}: {} elements", now(), snapshot.len());
+    match changes {
+        None => entry += ", first snapshot\n",
+        Some(changes) => {
+            entry += &format!(", {}\n", snapshot::summary(changes));
+            for change in changes {
+                entry += &format!("  {change}\n");
+            }
+        }
+    }
+    append(path, &entry)
+}
+
+fn append(path: &str, content: &str) -> Result<(), SrpError> {
+    std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .and_then(|mut o| o.write_all(content.as_bytes()))
+        .map_err(|e| SrpError::Filesystem(format!("Failed to write the change log {path}: {e}")))
+}
+
+fn now() -> String {
+    format!("[{}]", chrono::Local::now().format("%Y-%m-%d %H:%M:%S"))
+}